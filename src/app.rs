@@ -0,0 +1,1257 @@
+//! The interactive simulation behind the terminal front-end, along with running it headless
+//! without displaying anything
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fmt, thread};
+
+use rand::distributions::{Bernoulli, BernoulliError};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::display::*;
+use crate::export::{self, ImageFormat, Theme};
+use crate::game::*;
+use crate::pattern::{self, Format, Pattern};
+
+/// Size of the grid if there's no preferred size, and the display doesn't have one either
+const FALLBACK_WIDTH: usize = 40;
+const FALLBACK_HEIGHT: usize = 20;
+/// Largest width or height of the grid to fall back on, so that a big pattern doesn't ask for more
+/// memory than there is. An unbounded grid is only a window onto the plane, so is capped instead.
+const MAX_FALLBACK_SIZE: usize = 1 << 14;
+
+#[derive(Debug)]
+pub enum Error {
+    Display(crossterm::ErrorKind),
+    Pattern(pattern::Error),
+    Export(io::Error),
+    /// The grid is too big to simulate, whether asked for or fallen back on to fit a pattern
+    GridTooBig(GridTooBigError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Action {
+    Restart,
+    Save,
+    /// Save an image of the current generation
+    Snapshot,
+    /// Stop or start displaying generations
+    TogglePause,
+    /// While paused, display the next generation
+    StepOnce,
+    /// While paused, display the generation `--step` generations later
+    Step,
+    /// Halve the period, or double the step once there's no period left to halve
+    Faster,
+    /// Halve the step, or double the period once there's no step left to halve
+    Slower,
+    /// Stop or start advancing as fast as possible, displaying only every so often
+    ToggleMaxSpeed,
+    /// Set the cells drawn at the terminal column and row
+    Draw(Cell, (u16, u16)),
+    /// Show a cursor to edit cells with, stopping the simulation until finished
+    Edit,
+    MoveCursor(Direction),
+    /// Kill the cell under the cursor if it's alive, or vice versa
+    ToggleCell,
+    /// Kill every cell
+    Clear,
+    StopEditing,
+    Pan(Direction),
+    ZoomIn,
+    ZoomOut,
+    /// Move the viewport to the middle of the live cells
+    Recentre,
+    Exit,
+    Unmapped,
+}
+
+pub struct Config {
+    pub start: usize,
+    pub step: usize,
+    /// Number of generations to display, including the first one, which is always displayed
+    pub count: usize,
+    pub paused: bool,
+    pub on_stable: OnStable,
+    pub preferred_size: Option<(usize, usize)>,
+    pub period: Duration,
+    pub rule: Rule,
+    pub topology: Topology,
+    pub save_format: Format,
+    pub theme: Theme,
+    pub image_format: ImageFormat,
+    pub snapshot_at: Option<usize>,
+    pub glyphs: Glyphs,
+    #[cfg(feature = "parallel")]
+    pub threads: usize,
+}
+
+/// Where the first generation comes from
+pub enum Seed {
+    /// Each cell is alive with probability `weight`, decided by a PRNG seeded with `seed`
+    Random {
+        seed: u64,
+        weight: f32,
+        distr: Bernoulli,
+    },
+    /// Placed at the given offset, or centred if there isn't one
+    Pattern(Pattern, Option<Position>),
+}
+
+/// How a `--headless` run ended
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Outcome {
+    /// Advanced through every generation
+    Finished,
+    /// Stopped early, as the generations started repeating
+    Stable,
+}
+
+/// What to do once the generations start repeating
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum OnStable {
+    Continue,
+    Stop,
+    Restart,
+}
+
+/// Hashes of the most recent generations, to spot when they start repeating
+struct History {
+    /// With the index of each generation, oldest first
+    hashes: VecDeque<(u64, usize)>,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum State {
+    Initial(Box<State>),
+    Running,
+    Paused,
+    /// Editing the cell under the cursor, then carrying on as before
+    Editing(Position, Box<State>),
+    Waiting(Duration, Box<State>),
+    Finished,
+}
+
+/// Maps input events from a [`Display`] to what the app should do about them
+pub trait Input {
+    fn action(self, editing: bool) -> Action;
+}
+
+pub struct App<D: Display> {
+    start: usize,
+    step: usize,
+    count: usize,
+    curr_count: usize,
+    size: (usize, usize),
+    period: Duration,
+    max_speed: bool,
+    last_render: Instant,
+    /// Generations advanced by each second, as of the last `rate_sample`
+    rate: Option<f64>,
+    /// When the rate was last measured, and the index of the generation at the time
+    rate_sample: (Instant, usize),
+    history: History,
+    /// Set once the generations start repeating, see [`Status::stable`]
+    stable: Option<(usize, usize)>,
+    on_stable: OnStable,
+    save_format: Format,
+    theme: Theme,
+    image_format: ImageFormat,
+    /// Index of the generation to save an image of once it's reached, until it has been
+    snapshot_at: Option<usize>,
+    /// Shown until the next one, see [`Status::message`]
+    message: Option<String>,
+    seed: Seed,
+    state: State,
+    generation: Generation,
+    viewport: Viewport,
+    display: D,
+}
+
+impl<D> App<D>
+where
+    D: Display,
+    D::Event: Input,
+    Error: From<D::Error>,
+{
+    /// Max time to wait for user input while paused or editing
+    const PAUSED_POLL_PERIOD: Duration = Duration::from_millis(20);
+    /// Time between displaying generations at max speed
+    const MAX_SPEED_RENDER_PERIOD: Duration = Duration::from_millis(100);
+    const MAX_PERIOD: Duration = Duration::from_secs(10);
+    /// Time to measure the rate over
+    const RATE_SAMPLE_PERIOD: Duration = Duration::from_secs(1);
+
+    pub fn new(config: Config, seed: Seed, display: D) -> Result<Self> {
+        let (width, height) = config
+            .preferred_size
+            .or_else(|| display.available_cells())
+            .map_or_else(|| seed.fallback_size(config.topology), Ok)?;
+
+        let seed_gen = seed.generate(width, height, config.rule, config.topology)?;
+        #[cfg(feature = "parallel")]
+        let seed_gen = seed_gen.with_threads(config.threads);
+        let generation = Generation::nth_after(&seed_gen, config.start);
+        let mut history = History::new();
+        history.record(&generation);
+        Ok(Self {
+            start: config.start,
+            step: config.step,
+            count: config.count.saturating_sub(1),
+            curr_count: config.count.saturating_sub(1),
+            size: (width, height),
+            period: config.period,
+            max_speed: false,
+            last_render: Instant::now(),
+            rate: None,
+            rate_sample: (Instant::now(), generation.index()),
+            history,
+            stable: None,
+            on_stable: config.on_stable,
+            save_format: config.save_format,
+            theme: config.theme,
+            image_format: config.image_format,
+            snapshot_at: config.snapshot_at,
+            message: None,
+            seed,
+            state: State::initial(config.paused),
+            generation,
+            viewport: Viewport::default(),
+            display,
+        })
+    }
+
+    pub fn run_to_completion(&mut self) -> Result<()> {
+        loop {
+            self.handle_input()?;
+            if let Some(index) = self.snapshot_at {
+                if self.generation.index() >= index {
+                    self.snapshot().map_err(Error::Export)?;
+                    self.snapshot_at = None;
+                }
+            }
+            match self.state.clone() {
+                State::Initial(next_state) => {
+                    self.render()?;
+                    self.state = State::Waiting(self.period, next_state);
+                }
+                State::Running if self.max_speed => {
+                    self.update()?;
+                    // display every so often, and the last generation once finished
+                    if self.last_render.elapsed() >= Self::MAX_SPEED_RENDER_PERIOD
+                        || self.state == State::Finished
+                    {
+                        self.render()?;
+                    }
+                }
+                State::Running => {
+                    self.update()?;
+                    self.render()?;
+                    self.state = State::Waiting(self.period, Box::new(self.state.clone()));
+                }
+                State::Paused | State::Editing(..) => {
+                    thread::sleep(Self::PAUSED_POLL_PERIOD);
+                }
+                State::Waiting(duration, next_state) => {
+                    self.wait(duration, next_state);
+                }
+                State::Finished => {
+                    break;
+                }
+            }
+        }
+        self.display.finish()?;
+        Ok(())
+    }
+
+    fn handle_input(&mut self) -> Result<()> {
+        while let Some(ev) = self.display.take_pending_event()? {
+            let editing = matches!(self.state, State::Editing(..));
+            match ev.action(editing) {
+                Action::Restart => {
+                    self.restart();
+                }
+                Action::Save => {
+                    // a file which can't be saved is no reason to lose the run it was meant to keep
+                    self.message = Some(match self.save() {
+                        Ok(path) => format!("Saved {}", path.display()),
+                        Err(err) => format!("Couldn't save: {}", err),
+                    });
+                    self.render()?;
+                }
+                Action::Snapshot => {
+                    // like saving, an image which can't be saved is no reason to lose the run
+                    self.message = Some(match self.snapshot() {
+                        Ok(path) => format!("Saved {}", path.display()),
+                        Err(err) => format!("Couldn't save image: {}", err),
+                    });
+                    self.render()?;
+                }
+                Action::TogglePause => {
+                    self.state = self.state.clone().toggle_paused();
+                }
+                Action::StepOnce if self.state.is_paused() => {
+                    self.generation = Generation::nth_after(&self.generation, 1);
+                    self.render()?;
+                }
+                Action::Step if self.state.is_paused() => {
+                    self.generation = Generation::nth_after(&self.generation, self.step);
+                    self.render()?;
+                }
+                Action::StepOnce | Action::Step => {}
+                Action::Faster => {
+                    self.faster();
+                    self.render()?;
+                }
+                Action::Slower => {
+                    self.slower();
+                    self.render()?;
+                }
+                Action::ToggleMaxSpeed => {
+                    self.max_speed = !self.max_speed;
+                    self.render()?;
+                }
+                Action::Draw(cell, at) => {
+                    if let Some(bounds) = self.display.cells_at(at, &self.viewport) {
+                        self.draw(cell, bounds);
+                        self.forget_history();
+                        self.render()?;
+                    }
+                }
+                Action::Edit => {
+                    let cursor = self.clamp_to_grid(self.viewport.centre(self.view_size()));
+                    let next_state = if self.state.is_paused() {
+                        State::Paused
+                    } else {
+                        State::Running
+                    };
+                    self.state = State::Editing(cursor, Box::new(next_state));
+                    self.render()?;
+                }
+                Action::MoveCursor(direction) => {
+                    self.move_cursor(direction);
+                    self.render()?;
+                }
+                Action::ToggleCell => {
+                    if let State::Editing(cursor, _) = self.state {
+                        self.generation.set(cursor, !self.generation[cursor]);
+                        self.forget_history();
+                        self.render()?;
+                    }
+                }
+                Action::Clear => {
+                    self.generation = self.generation.blank(self.generation.index());
+                    self.forget_history();
+                    self.render()?;
+                }
+                Action::StopEditing => {
+                    if let State::Editing(_, next_state) = self.state.clone() {
+                        self.state = *next_state;
+                        self.render()?;
+                    }
+                }
+                Action::Pan(direction) => {
+                    self.viewport.pan(direction);
+                    self.render()?;
+                }
+                Action::ZoomIn => {
+                    self.viewport.zoom_in(self.view_size());
+                    self.render()?;
+                }
+                Action::ZoomOut => {
+                    self.viewport.zoom_out(self.view_size());
+                    self.render()?;
+                }
+                Action::Recentre => {
+                    self.recentre();
+                    self.render()?;
+                }
+                Action::Exit => {
+                    self.state = State::Finished;
+                    break;
+                }
+                Action::Unmapped => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn faster(&mut self) {
+        let period_millis = self.period.as_millis() as u64;
+        if period_millis != 0 {
+            self.period = Duration::from_millis(period_millis / 2);
+        } else {
+            self.step = self.step.saturating_mul(2);
+        }
+    }
+
+    fn slower(&mut self) {
+        if self.step > 1 {
+            self.step /= 2;
+        } else if self.period.as_millis() == 0 {
+            self.period = Duration::from_millis(1);
+        } else {
+            self.period = (self.period * 2).min(Self::MAX_PERIOD);
+        }
+    }
+
+    /// Number of cells which fit in the display when zoomed all the way in
+    fn view_size(&self) -> (usize, usize) {
+        self.display.available_cells().unwrap_or(self.size)
+    }
+
+    fn recentre(&mut self) {
+        if let Some((min, max)) = self.generation.bounds() {
+            // the bottom-right corner of the bounds is exclusive
+            let centre = Position((min.x() + max.x() - 1) / 2, (min.y() + max.y() - 1) / 2);
+            self.viewport.centre_on(centre, self.view_size());
+        }
+    }
+
+    /// Move the cursor by one dot's worth of cells, and the viewport too if the cursor
+    /// would go out of view
+    fn move_cursor(&mut self, direction: Direction) {
+        let cursor = match self.state {
+            State::Editing(cursor, _) => cursor,
+            _ => return,
+        };
+        let distance = self.viewport.scale() as isize;
+        let next_cursor = cursor
+            + match direction {
+                Direction::Up => Position(0, -distance),
+                Direction::Down => Position(0, distance),
+                Direction::Left => Position(-distance, 0),
+                Direction::Right => Position(distance, 0),
+            };
+        let next_cursor = self.clamp_to_grid(next_cursor);
+        if let State::Editing(cursor, _) = &mut self.state {
+            *cursor = next_cursor;
+        }
+
+        let (width, height) = self.view_size();
+        let min = self.viewport.origin();
+        let max = min + Position(width as isize * distance, height as isize * distance);
+        if !(min.x()..max.x()).contains(&next_cursor.x())
+            || !(min.y()..max.y()).contains(&next_cursor.y())
+        {
+            self.viewport.pan(direction);
+        }
+    }
+
+    /// The closest position to `position` which is on the grid
+    fn clamp_to_grid(&self, position: Position) -> Position {
+        if self.generation.topology() == Topology::Unbounded {
+            return position;
+        }
+        let max_x = self.generation.width().saturating_sub(1) as isize;
+        let max_y = self.generation.height().saturating_sub(1) as isize;
+        Position(position.x().clamp(0, max_x), position.y().clamp(0, max_y))
+    }
+
+    /// Set every cell from `min` (inclusive) to `max` (exclusive) which is on the grid
+    fn draw(&mut self, cell: Cell, (mut min, mut max): (Position, Position)) {
+        if self.generation.topology() != Topology::Unbounded {
+            let size = Position(
+                self.generation.width() as isize,
+                self.generation.height() as isize,
+            );
+            min = Position(min.x().max(0), min.y().max(0));
+            max = Position(max.x().min(size.x()), max.y().min(size.y()));
+        }
+        for y in min.y()..max.y() {
+            for x in min.x()..max.x() {
+                self.generation.set(Position(x, y), cell);
+            }
+        }
+    }
+
+    /// Save the current generation to a new file in the working directory, returning its path
+    fn save(&self) -> Result<PathBuf> {
+        let mut pattern = Pattern::from(&self.generation);
+        self.seed.annotate(&mut pattern, &self.generation);
+
+        let path = self.new_file_path(self.save_format.extension());
+        pattern::write(&path, self.save_format, &pattern)?;
+        Ok(path)
+    }
+
+    /// Save an image of the current generation to a new file in the working directory, returning
+    /// its path
+    fn snapshot(&self) -> io::Result<PathBuf> {
+        let path = self.new_file_path(self.image_format.extension());
+        export::write(&path, self.image_format, &self.generation, &self.theme)?;
+        Ok(path)
+    }
+
+    /// In the working directory, named after the current generation and time
+    fn new_file_path(&self, extension: &str) -> PathBuf {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        PathBuf::from(format!(
+            "generation-{}-{}.{}",
+            timestamp,
+            self.generation.index(),
+            extension
+        ))
+    }
+
+    fn restart(&mut self) {
+        let (width, height) = self.size;
+        self.seed.reseed();
+        let seed_gen = self
+            .seed
+            .generate(
+                width,
+                height,
+                self.generation.rule(),
+                self.generation.topology(),
+            )
+            .expect("Expected a grid the same size as the first one to fit in memory");
+        #[cfg(feature = "parallel")]
+        let seed_gen = seed_gen.with_threads(self.generation.threads());
+        self.generation = Generation::nth_after(&seed_gen, self.start);
+        self.forget_history();
+        self.curr_count = self.count;
+        self.state = State::initial(self.state.is_paused());
+    }
+
+    /// Stop looking for repeats of any generations before this one, e.g. after it's edited
+    fn forget_history(&mut self) {
+        self.history.clear();
+        self.history.record(&self.generation);
+        self.stable = None;
+    }
+
+    fn update(&mut self) -> Result<()> {
+        if self.curr_count != 0 {
+            self.curr_count -= 1;
+            self.generation = Generation::nth_after(&self.generation, self.step);
+            if self.stable.is_none() {
+                self.look_for_repeats();
+            }
+        } else {
+            self.state = State::Finished;
+        }
+        Ok(())
+    }
+
+    fn look_for_repeats(&mut self) {
+        self.stable = self.history.record(&self.generation);
+        if self.stable.is_none() {
+            return;
+        }
+        match self.on_stable {
+            OnStable::Continue => {}
+            OnStable::Stop => self.state = State::Finished,
+            OnStable::Restart => self.restart(),
+        }
+    }
+
+    fn render(&mut self) -> Result<()> {
+        let (sample_time, sample_index) = self.rate_sample;
+        let index = self.generation.index();
+        if index < sample_index {
+            // restarted, so start measuring again
+            self.rate = None;
+            self.rate_sample = (Instant::now(), index);
+        } else if sample_time.elapsed() >= Self::RATE_SAMPLE_PERIOD {
+            let generations = (index - sample_index) as f64;
+            self.rate = Some(generations / sample_time.elapsed().as_secs_f64());
+            self.rate_sample = (Instant::now(), index);
+        }
+
+        let status = Status {
+            period: self.period,
+            step: self.step,
+            max_speed: self.max_speed,
+            cursor: match self.state {
+                State::Editing(cursor, _) => Some(cursor),
+                _ => None,
+            },
+            rate: self.rate,
+            stable: self.stable,
+            message: self.message.clone(),
+        };
+        self.last_render = Instant::now();
+        self.display
+            .draw(&self.generation, &self.viewport, &status)
+            .map_err(Error::from)
+    }
+
+    fn wait(&mut self, duration: Duration, next_state: Box<State>) {
+        /// Max time to sleep without checking for and handling any buffered user input
+        static MAX_WAIT_TIME: Duration = Duration::from_millis(250);
+
+        // could sleep for longer than `duration` but the inaccuracy shouldn't be enough to care
+        // about right now
+        if duration > MAX_WAIT_TIME {
+            thread::sleep(MAX_WAIT_TIME);
+            self.state = State::Waiting(duration - MAX_WAIT_TIME, next_state);
+        } else {
+            thread::sleep(duration);
+            self.state = *next_state;
+        }
+    }
+}
+
+/// Advance through the generations on a `display` which doesn't need a terminal, then write the
+/// last one to the `output` file, or to stdout if there isn't one
+pub fn run_headless<D>(
+    config: Config,
+    seed: Seed,
+    display: D,
+    output: Option<&Path>,
+) -> Result<Outcome>
+where
+    D: Display,
+    D::Event: Input,
+    Error: From<D::Error>,
+{
+    let save_format = config.save_format;
+    // there's nobody watching, so there's no point in waiting between generations
+    let config = Config {
+        period: Duration::ZERO,
+        ..config
+    };
+    let mut app = App::new(config, seed, display)?;
+    app.run_to_completion()?;
+
+    let mut pattern = Pattern::from(&app.generation);
+    app.seed.annotate(&mut pattern, &app.generation);
+    match output {
+        Some(path) => pattern::write(path, save_format, &pattern)?,
+        None => {
+            let mut out = io::stdout();
+            save_format.write(&pattern, &mut out)?;
+            out.flush().map_err(pattern::Error::from)?;
+        }
+    }
+    Ok(match (app.stable, app.on_stable) {
+        (Some(_), OnStable::Stop) => Outcome::Stable,
+        _ => Outcome::Finished,
+    })
+}
+
+impl History {
+    /// Max number of generations to remember
+    const LEN: usize = 64;
+
+    fn new() -> Self {
+        Self {
+            hashes: VecDeque::with_capacity(Self::LEN),
+        }
+    }
+
+    fn hash(generation: &Generation) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        generation.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Remember the `generation`, forgetting the oldest one if there are too many. If it looks
+    /// the same as one of the others, returns the index of the last one which did and how
+    /// often they repeat.
+    fn record(&mut self, generation: &Generation) -> Option<(usize, usize)> {
+        let hash = Self::hash(generation);
+        let repeat_of = self
+            .hashes
+            .iter()
+            .rev()
+            .find(|&&(prev_hash, _)| prev_hash == hash)
+            .map(|&(_, index)| index);
+        if self.hashes.len() == Self::LEN {
+            self.hashes.pop_front();
+        }
+        self.hashes.push_back((hash, generation.index()));
+        let repeat_of = repeat_of?;
+
+        // not every generation is necessarily recorded, so the real period could be a fraction
+        // of the number of generations between the repeats
+        let max_period = generation.index() - repeat_of;
+        let mut next = generation.clone();
+        for period in 1..max_period.min(Self::LEN) {
+            next = Generation::nth_after(&next, 1);
+            if max_period.is_multiple_of(period) && Self::hash(&next) == hash {
+                return Some((repeat_of, period));
+            }
+        }
+        Some((repeat_of, max_period))
+    }
+
+    fn clear(&mut self) {
+        self.hashes.clear();
+    }
+}
+
+impl State {
+    /// Display the first generation, then carry on running unless `paused`
+    fn initial(paused: bool) -> Self {
+        let next_state = if paused { Self::Paused } else { Self::Running };
+        Self::Initial(Box::new(next_state))
+    }
+
+    /// Whether this is, or will next settle into, the `Paused` state
+    fn is_paused(&self) -> bool {
+        match self {
+            Self::Paused => true,
+            Self::Initial(next_state) | Self::Waiting(_, next_state) => next_state.is_paused(),
+            Self::Running | Self::Editing(..) | Self::Finished => false,
+        }
+    }
+
+    fn toggle_paused(self) -> Self {
+        match self {
+            Self::Running => Self::Paused,
+            Self::Paused => Self::Running,
+            Self::Initial(next_state) => Self::Initial(Box::new(next_state.toggle_paused())),
+            Self::Waiting(duration, next_state) => {
+                Self::Waiting(duration, Box::new(next_state.toggle_paused()))
+            }
+            Self::Editing(..) | Self::Finished => self,
+        }
+    }
+}
+
+impl Seed {
+    pub fn random(seed: u64, weight: f32) -> std::result::Result<Self, BernoulliError> {
+        let distr = Bernoulli::new(weight.into())?;
+        Ok(Self::Random {
+            seed,
+            weight,
+            distr,
+        })
+    }
+
+    /// Size of the grid if there's no preferred size, and the display doesn't have one either. A
+    /// pattern gets enough room to move or grow by its own size in every direction from where it's
+    /// placed, so that bounded grids don't get in its way straight away.
+    fn fallback_size(
+        &self,
+        topology: Topology,
+    ) -> std::result::Result<(usize, usize), GridTooBigError> {
+        let (width, height) = match self {
+            Self::Random { .. } => (0, 0),
+            Self::Pattern(pattern, offset) => {
+                let offset = offset.unwrap_or(Position(0, 0));
+                (
+                    (pattern.width().saturating_mul(3)).saturating_add(offset.x().max(0) as usize),
+                    (pattern.height().saturating_mul(3)).saturating_add(offset.y().max(0) as usize),
+                )
+            }
+        };
+        let (width, height) = (width.max(FALLBACK_WIDTH), height.max(FALLBACK_HEIGHT));
+        match topology {
+            Topology::Unbounded => {
+                Ok((width.min(MAX_FALLBACK_SIZE), height.min(MAX_FALLBACK_SIZE)))
+            }
+            _ if width > MAX_FALLBACK_SIZE || height > MAX_FALLBACK_SIZE => {
+                Err(GridTooBigError(width, height))
+            }
+            _ => Ok((width, height)),
+        }
+    }
+
+    fn generate(
+        &self,
+        width: usize,
+        height: usize,
+        rule: Rule,
+        topology: Topology,
+    ) -> std::result::Result<Generation, GridTooBigError> {
+        match self {
+            Self::Random { seed, distr, .. } => {
+                let mut cell_gen = weighted_cell_generator(*distr, *seed);
+                Ok(Generation::generate(0, width, height, rule, &mut cell_gen)?
+                    .with_topology(topology))
+            }
+            Self::Pattern(pattern, offset) => {
+                // an unbounded grid is only a window onto the plane, so there's no need to ever
+                // have every cell of it
+                let mut generation = match topology {
+                    Topology::Unbounded => {
+                        Generation::from_universe(width, height, Universe::new(0, rule, &[]))
+                    }
+                    _ => Generation::filled(0, width, height, rule, Cell::Dead)?
+                        .with_topology(topology),
+                };
+                pattern.place(&mut generation, *offset);
+                Ok(generation)
+            }
+        }
+    }
+
+    /// Move on to a different (but still reproducible) first generation
+    fn reseed(&mut self) {
+        if let Self::Random { seed, .. } = self {
+            *seed = SmallRng::seed_from_u64(*seed).gen();
+        }
+    }
+
+    /// Record enough about where `generation` came from to be able to reproduce it
+    fn annotate(&self, pattern: &mut Pattern, generation: &Generation) {
+        pattern.push_comment(format!("Generation: {}", generation.index()));
+        pattern.push_comment(format!("Rule: {}", generation.rule()));
+        match self {
+            Self::Random { seed, weight, .. } => {
+                pattern.push_comment(format!("Seed: {}, weight: {}", seed, weight));
+                pattern.push_comment(format!(
+                    "Reproduce with: {} --seed {} --weight {} --rule {} --topology {} --width {} --height {} --start {}",
+                    env!("CARGO_PKG_NAME"),
+                    seed,
+                    weight,
+                    generation.rule(),
+                    generation.topology(),
+                    generation.width(),
+                    generation.height(),
+                    generation.index()
+                ));
+            }
+            Self::Pattern(seed_pattern, _) => {
+                pattern.set_name(seed_pattern.name().map(String::from));
+                pattern.set_author(seed_pattern.author().map(String::from));
+            }
+        }
+    }
+}
+
+fn weighted_cell_generator(distr: Bernoulli, seed: u64) -> impl FnMut() -> Cell {
+    let rng = SmallRng::seed_from_u64(seed);
+    let mut cell_iter =
+        rng.sample_iter::<bool, _>(distr)
+            .map(|alive| if alive { Cell::Alive } else { Cell::Dead });
+    move || {
+        cell_iter
+            .next()
+            .expect("Expected Rng::sample_iter(Distribution) to be infinite")
+    }
+}
+
+impl From<Event> for Action {
+    fn from(ev: Event) -> Self {
+        match ev {
+            Event::Key(key_ev) => Self::from(key_ev),
+            Event::Mouse(mouse_ev) => Self::from(mouse_ev),
+            _ => Self::Unmapped,
+        }
+    }
+}
+
+impl From<KeyEvent> for Action {
+    fn from(key_ev: KeyEvent) -> Self {
+        match key_ev.code {
+            KeyCode::Char('r') => Self::Restart,
+            KeyCode::Char('p') => Self::Save,
+            KeyCode::Char('i') => Self::Snapshot,
+            KeyCode::Char(' ') => Self::TogglePause,
+            KeyCode::Char('n') | KeyCode::Char('.') => Self::StepOnce,
+            KeyCode::Char('N') | KeyCode::Char('>') => Self::Step,
+            KeyCode::Char(']') => Self::Faster,
+            KeyCode::Char('[') => Self::Slower,
+            KeyCode::Char('m') => Self::ToggleMaxSpeed,
+            KeyCode::Up | KeyCode::Char('w') => Self::Pan(Direction::Up),
+            KeyCode::Down | KeyCode::Char('s') => Self::Pan(Direction::Down),
+            KeyCode::Left | KeyCode::Char('a') => Self::Pan(Direction::Left),
+            KeyCode::Right | KeyCode::Char('d') => Self::Pan(Direction::Right),
+            KeyCode::Char('+') | KeyCode::Char('=') => Self::ZoomIn,
+            KeyCode::Char('-') => Self::ZoomOut,
+            KeyCode::Char('q') | KeyCode::Esc => Self::Exit,
+            KeyCode::Char('c') if key_ev.modifiers.contains(KeyModifiers::CONTROL) => Self::Exit,
+            KeyCode::Char('c') => Self::Recentre,
+            KeyCode::Char('e') => Self::Edit,
+            _ => Self::Unmapped,
+        }
+    }
+}
+
+impl Input for Event {
+    fn action(self, editing: bool) -> Action {
+        if editing {
+            Action::editing(self)
+        } else {
+            Action::from(self)
+        }
+    }
+}
+
+/// For displays which never have any input
+impl Input for Infallible {
+    fn action(self, _editing: bool) -> Action {
+        match self {}
+    }
+}
+
+impl Action {
+    /// Like [`Action::from`], but some keys do different things while editing
+    fn editing(ev: Event) -> Self {
+        let key_ev = match ev {
+            Event::Key(key_ev) => key_ev,
+            _ => return Self::from(ev),
+        };
+        match key_ev.code {
+            KeyCode::Up | KeyCode::Char('k') => Self::MoveCursor(Direction::Up),
+            KeyCode::Down | KeyCode::Char('j') => Self::MoveCursor(Direction::Down),
+            KeyCode::Left | KeyCode::Char('h') => Self::MoveCursor(Direction::Left),
+            KeyCode::Right | KeyCode::Char('l') => Self::MoveCursor(Direction::Right),
+            KeyCode::Char(' ') => Self::ToggleCell,
+            KeyCode::Char('x') => Self::Clear,
+            KeyCode::Char('e') | KeyCode::Esc => Self::StopEditing,
+            _ => Self::from(key_ev),
+        }
+    }
+}
+
+impl From<MouseEvent> for Action {
+    fn from(mouse_ev: MouseEvent) -> Self {
+        match mouse_ev {
+            MouseEvent::Down(button, column, row, _) | MouseEvent::Drag(button, column, row, _) => {
+                match button {
+                    MouseButton::Left => Self::Draw(Cell::Alive, (column, row)),
+                    MouseButton::Right => Self::Draw(Cell::Dead, (column, row)),
+                    MouseButton::Middle => Self::Unmapped,
+                }
+            }
+            _ => Self::Unmapped,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Display(err) => Some(err),
+            Self::Pattern(err) => Some(err),
+            Self::Export(err) => Some(err),
+            Self::GridTooBig(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Display(err) => fmt::Display::fmt(err, f),
+            Self::Pattern(err) => fmt::Display::fmt(err, f),
+            Self::Export(err) => write!(f, "couldn't export: {}", err),
+            Self::GridTooBig(err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl From<crossterm::ErrorKind> for Error {
+    fn from(source: crossterm::ErrorKind) -> Self {
+        Self::Display(source)
+    }
+}
+
+impl From<Infallible> for Error {
+    fn from(source: Infallible) -> Self {
+        match source {}
+    }
+}
+
+impl From<GridTooBigError> for Error {
+    fn from(source: GridTooBigError) -> Self {
+        Self::GridTooBig(source)
+    }
+}
+
+impl From<pattern::Error> for Error {
+    fn from(source: pattern::Error) -> Self {
+        Self::Pattern(source)
+    }
+}
+
+impl<E> From<RecordingError<E>> for Error
+where
+    Error: From<E>,
+{
+    fn from(source: RecordingError<E>) -> Self {
+        match source {
+            RecordingError::Display(err) => Self::from(err),
+            RecordingError::Io(err) => Self::Export(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    /// Plays back a fixed list of events, then exits, and records everything it's asked to draw
+    struct ScriptedDisplay {
+        /// `None` stands for there being no more events until the app has moved on
+        events: RefCell<VecDeque<Option<Action>>>,
+        size: (usize, usize),
+        drawn: Vec<Drawn>,
+    }
+
+    /// What a [`ScriptedDisplay`] was asked to draw
+    struct Drawn {
+        index: usize,
+        cells: Vec<Position>,
+        status: Status,
+    }
+
+    impl ScriptedDisplay {
+        fn new(size: (usize, usize), events: Vec<Option<Action>>) -> Self {
+            Self {
+                events: RefCell::new(events.into()),
+                size,
+                drawn: Vec::new(),
+            }
+        }
+    }
+
+    impl Display for ScriptedDisplay {
+        type Event = Action;
+        type Error = Infallible;
+
+        fn available_cells(&self) -> Option<(usize, usize)> {
+            Some(self.size)
+        }
+
+        fn take_pending_event(&self) -> std::result::Result<Option<Action>, Infallible> {
+            Ok(self
+                .events
+                .borrow_mut()
+                .pop_front()
+                .unwrap_or(Some(Action::Exit)))
+        }
+
+        fn cells_at(&self, _at: (u16, u16), _viewport: &Viewport) -> Option<(Position, Position)> {
+            None
+        }
+
+        fn draw(
+            &mut self,
+            gen: &Generation,
+            _viewport: &Viewport,
+            status: &Status,
+        ) -> std::result::Result<(), Infallible> {
+            self.drawn.push(Drawn {
+                index: gen.index(),
+                cells: gen.live_cells(None),
+                status: status.clone(),
+            });
+            Ok(())
+        }
+    }
+
+    /// Scripted events are already actions, whether editing or not
+    impl Input for Action {
+        fn action(self, _editing: bool) -> Action {
+            self
+        }
+    }
+
+    /// Play back the `events` on an 8x6 grid, until they run out or the app finishes
+    fn run_scripted(config: Config, seed: Seed, events: Vec<Option<Action>>) -> Vec<Drawn> {
+        let size = (8, 6);
+        let config = Config {
+            preferred_size: Some(size),
+            ..config
+        };
+        let mut app = App::new(config, seed, ScriptedDisplay::new(size, events)).unwrap();
+        app.run_to_completion().unwrap();
+        app.display.drawn
+    }
+
+    /// Runs without waiting between generations, and stops after `count` of them
+    fn config(count: usize, topology: Topology, on_stable: OnStable) -> Config {
+        Config {
+            start: 0,
+            step: 1,
+            count,
+            paused: false,
+            on_stable,
+            preferred_size: None,
+            period: Duration::ZERO,
+            rule: Rule::CONWAY,
+            topology,
+            save_format: Format::Rle,
+            theme: Theme::default(),
+            image_format: ImageFormat::Png,
+            snapshot_at: None,
+            glyphs: Glyphs::Block,
+            #[cfg(feature = "parallel")]
+            threads: 1,
+        }
+    }
+
+    fn glider_seed() -> Seed {
+        rle_seed("x = 3, y = 3\nbo$2bo$3o!")
+    }
+
+    fn rle_seed(content: &str) -> Seed {
+        Seed::Pattern(Format::Rle.parse(content).unwrap(), None)
+    }
+
+    /// Oscillates with period 2, on a 5x5 grid
+    fn blinker() -> Generation {
+        let mut generation = Generation::filled(0, 5, 5, Rule::CONWAY, Cell::Dead).unwrap();
+        for x in 1..=3 {
+            generation.set(Position(x, 2), Cell::Alive);
+        }
+        generation
+    }
+
+    #[test]
+    fn a_glider_run_headless_is_still_a_glider() {
+        for &(topology, on_stable) in &[
+            (Topology::Torus, OnStable::Continue),
+            (Topology::Plane, OnStable::Stop),
+        ] {
+            let config = config(5, topology, on_stable);
+            let mut app = App::new(config, glider_seed(), HeadlessDisplay).unwrap();
+            app.run_to_completion().unwrap();
+            assert_eq!(app.generation.index(), 4);
+            assert_eq!(app.stable, None, "{}", topology);
+            assert_eq!(shape(&app.generation), pattern::glider());
+        }
+    }
+
+    #[test]
+    fn steps_only_while_paused() {
+        let config = Config {
+            step: 3,
+            ..config(usize::MAX, Topology::Torus, OnStable::Continue)
+        };
+        let events = vec![
+            None,
+            None,
+            // ignored, as it's still running
+            Some(Action::StepOnce),
+            Some(Action::TogglePause),
+            Some(Action::StepOnce),
+            Some(Action::Step),
+            None,
+            Some(Action::TogglePause),
+            None,
+        ];
+        let drawn = run_scripted(config, glider_seed(), events);
+        let indices = drawn.iter().map(|drawn| drawn.index).collect::<Vec<_>>();
+        assert_eq!(indices, [0, 1, 4, 7]);
+    }
+
+    #[test]
+    fn faster_and_slower_trade_off_the_period_and_the_step() {
+        let config = Config {
+            paused: true,
+            period: Duration::from_millis(4),
+            ..config(usize::MAX, Topology::Torus, OnStable::Continue)
+        };
+        let mut events = vec![Some(Action::Faster); 4];
+        events.extend(vec![Some(Action::Slower); 3]);
+        let drawn = run_scripted(config, glider_seed(), events);
+        let speeds = drawn
+            .iter()
+            .map(|drawn| (drawn.status.period.as_millis(), drawn.status.step))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            speeds,
+            [(2, 1), (1, 1), (0, 1), (0, 2), (0, 1), (1, 1), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn the_cursor_edits_cells_without_leaving_the_grid() {
+        let config = Config {
+            paused: true,
+            ..config(usize::MAX, Topology::Torus, OnStable::Continue)
+        };
+        let mut events = vec![
+            Some(Action::Edit),
+            Some(Action::MoveCursor(Direction::Right)),
+            Some(Action::ToggleCell),
+        ];
+        // one more than it takes to reach the left edge
+        events.extend(vec![Some(Action::MoveCursor(Direction::Left)); 6]);
+        events.extend(vec![
+            Some(Action::ToggleCell),
+            Some(Action::Clear),
+            Some(Action::StopEditing),
+        ]);
+        let drawn = run_scripted(config, rle_seed("x = 0, y = 0\n!"), events);
+
+        // starts in the middle of the grid
+        assert_eq!(drawn[0].status.cursor, Some(Position(4, 3)));
+        assert_eq!(drawn[2].cells, [Position(5, 3)]);
+        assert_eq!(drawn[8].status.cursor, Some(Position(0, 3)));
+        assert_eq!(drawn[9].cells, [Position(0, 3), Position(5, 3)]);
+        assert_eq!(drawn[10].cells, []);
+        assert_eq!(drawn[11].status.cursor, None);
+        assert_eq!(drawn.len(), 12);
+    }
+
+    #[test]
+    fn history_finds_the_period_of_an_oscillator() {
+        let mut history = History::new();
+        assert_eq!(history.record(&blinker()), None);
+        assert_eq!(history.record(&Generation::nth_after(&blinker(), 1)), None);
+        assert_eq!(
+            history.record(&Generation::nth_after(&blinker(), 2)),
+            Some((0, 2))
+        );
+    }
+
+    #[test]
+    fn history_finds_the_period_of_an_oscillator_between_the_generations_it_records() {
+        // only every third generation, so the first repeat it sees is 6 generations later
+        let mut history = History::new();
+        for n in &[0, 3] {
+            assert_eq!(history.record(&Generation::nth_after(&blinker(), *n)), None);
+        }
+        assert_eq!(
+            history.record(&Generation::nth_after(&blinker(), 6)),
+            Some((0, 2))
+        );
+    }
+
+    #[test]
+    fn stops_once_the_generations_start_repeating() {
+        let config = config(usize::MAX, Topology::Torus, OnStable::Stop);
+        let drawn = run_scripted(config, rle_seed("x = 3, y = 1\n3o!"), vec![None; 16]);
+        let last = drawn.last().unwrap();
+        assert_eq!(last.index, 2);
+        assert_eq!(last.status.stable, Some((0, 2)));
+    }
+
+    #[test]
+    fn a_count_of_zero_still_displays_the_first_generation() {
+        let indices = |count| {
+            let config = config(count, Topology::Torus, OnStable::Continue);
+            let drawn = run_scripted(config, glider_seed(), vec![None; 4]);
+            drawn.iter().map(|drawn| drawn.index).collect::<Vec<_>>()
+        };
+        assert!(indices(0).iter().all(|&index| index == 0));
+        assert_eq!(indices(0), indices(1));
+    }
+
+    #[test]
+    fn falls_back_on_a_grid_only_as_big_as_fits_in_memory() {
+        let seed = rle_seed("x = 65536, y = 3\n3o!");
+        assert_eq!(
+            seed.fallback_size(Topology::Torus),
+            Err(GridTooBigError(3 * 65536, FALLBACK_HEIGHT))
+        );
+        assert_eq!(
+            seed.fallback_size(Topology::Unbounded),
+            Ok((MAX_FALLBACK_SIZE, FALLBACK_HEIGHT))
+        );
+        assert_eq!(
+            Generation::filled(0, usize::MAX, 64, Rule::CONWAY, Cell::Dead).err(),
+            Some(GridTooBigError(usize::MAX, 64))
+        );
+    }
+}
@@ -1,45 +1,358 @@
-use std::io::{self, Write};
-use std::mem;
+use std::collections::hash_map::DefaultHasher;
+use std::convert::Infallible;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufWriter, Stdout, Write};
+use std::str::FromStr;
 use std::time::Duration;
+use std::{error, fmt};
 
 use crossterm::cursor::{self, MoveTo};
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
 pub use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent};
-use crossterm::style::{Colorize, PrintStyledContent, Styler};
+use crossterm::style::{Colorize, Print, PrintStyledContent, Styler};
 use crossterm::terminal::{self, Clear, ClearType};
 use crossterm::{event, execute, queue};
 
+use crate::export::{asciicast, gif};
 use crate::game::*;
 
 type Result<T> = std::result::Result<T, crossterm::ErrorKind>;
 
-pub trait Display<Ev, Er> {
+pub trait Display {
+    /// Of user input
+    type Event;
+    type Error;
+
     fn available_cells(&self) -> Option<(usize, usize)>;
-    fn take_pending_event(&self) -> std::result::Result<Option<Ev>, Er>;
-    fn draw(&mut self, gen: &Generation) -> std::result::Result<(), Er>;
+    fn take_pending_event(&self) -> std::result::Result<Option<Self::Event>, Self::Error>;
+    /// The cells drawn as a single dot of the character at the column and row `at` within the
+    /// `viewport`, from the min (inclusive) to the max (exclusive) position, or `None` if there
+    /// aren't any
+    fn cells_at(&self, at: (u16, u16), viewport: &Viewport) -> Option<(Position, Position)>;
+    fn draw(
+        &mut self,
+        gen: &Generation,
+        viewport: &Viewport,
+        status: &Status,
+    ) -> std::result::Result<(), Self::Error>;
+    /// Called once after the last generation has been drawn, e.g. to write out anything which is
+    /// still pending
+    fn finish(&mut self) -> std::result::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// How the generations are being displayed, to show alongside them
+#[derive(Clone, PartialEq, Debug)]
+pub struct Status {
+    /// Duration to pause after displaying each generation
+    pub period: Duration,
+    /// Number of generations to advance by between each one which is displayed
+    pub step: usize,
+    /// Generations are being advanced as fast as possible, and only displayed every so often
+    pub max_speed: bool,
+    /// The cell being edited, if any
+    pub cursor: Option<Position>,
+    /// Number of generations actually being advanced by each second, once it's been measured
+    pub rate: Option<f64>,
+    /// The generation at which the generations started repeating, and how often they repeat
+    pub stable: Option<(usize, usize)>,
+    /// For the user about something which just happened, e.g. a file which couldn't be saved
+    pub message: Option<String>,
+}
+
+/// Which part of a generation is drawn, and how far zoomed out
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Viewport {
+    /// Of the top-left cell which is drawn
+    origin: Position,
+    /// Width (and height) of each block of cells which is drawn as a single dot of a character
+    scale: usize,
+}
+
+/// How many dots are packed into each character, and what they look like
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Glyphs {
+    /// One dot per character, drawn as `'•'`
+    Block,
+    /// Two dots per character, one above the other, drawn with half-blocks like `'▀'`
+    Half,
+    /// Eight dots per character, two across and four down, drawn with braille patterns like `'⢕'`
+    Braille,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseGlyphsError;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
 pub struct TerminalDisplay {
-    prev_gen: Option<Generation>,
+    glyphs: Glyphs,
+    prev_frame: Option<Frame>,
+    out: Output,
+}
+
+/// Doesn't display anything or take any input, for when there's no terminal to use
+pub struct HeadlessDisplay;
+
+/// Draws to another display, and records each generation drawn as the next frame of a GIF
+pub struct GifDisplay<D, W: Write> {
+    display: D,
+    encoder: gif::Encoder<W>,
+    /// To show each frame for
+    delay: Duration,
+    /// Index and hash of the generation in the last frame, so that it isn't recorded again when
+    /// it's only being redrawn, e.g. after panning
+    prev_frame: Option<(usize, u64)>,
+}
+
+#[derive(Debug)]
+pub enum RecordingError<E> {
+    /// From the display being recorded
+    Display(E),
+    Io(io::Error),
+}
+
+/// Where everything is drawn to: stdout, and a recording of it too if there is one
+struct Output {
+    stdout: Stdout,
+    recording: Option<asciicast::Recorder<BufWriter<File>>>,
+}
+
+/// What was drawn last time, so that only the characters which change need to be drawn next time
+struct Frame {
+    index: usize,
+    status: Status,
+    /// In characters
+    size: (usize, usize),
+    /// One for each character, row by row
+    shades: Vec<Shade>,
+    /// Of the character which is highlighted, if any
+    cursor_idx: Option<usize>,
+    /// Statistics about the generation, shown below it
+    status_line: String,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum Shade {
+    /// Bit `n` is set if at least one of the cells in the block drawn as dot `n` is alive, see
+    /// [`Glyphs::dot_bit`]
+    Dots(u8),
+    /// Every block in the character is completely outside of a bounded grid
+    OffGrid,
+}
+
+impl Viewport {
+    const MAX_SCALE: usize = 64;
+    /// Number of dots to move by when panning
+    const PAN_DISTANCE: isize = 8;
+
+    pub fn origin(&self) -> Position {
+        self.origin
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    pub fn pan(&mut self, direction: Direction) {
+        let distance = Self::PAN_DISTANCE * self.scale as isize;
+        self.origin += match direction {
+            Direction::Up => Position(0, -distance),
+            Direction::Down => Position(0, distance),
+            Direction::Left => Position(-distance, 0),
+            Direction::Right => Position(distance, 0),
+        };
+    }
+
+    /// Show each cell as a bigger part of a `size` dot view, keeping the same cell in the middle
+    /// of it
+    pub fn zoom_in(&mut self, size: (usize, usize)) {
+        let centre = self.centre(size);
+        self.scale = (self.scale / 2).max(1);
+        self.centre_on(centre, size);
+    }
+
+    /// Show more cells in a `size` dot view, keeping the same cell in the middle of it
+    pub fn zoom_out(&mut self, size: (usize, usize)) {
+        let centre = self.centre(size);
+        self.scale = (self.scale * 2).min(Self::MAX_SCALE);
+        self.centre_on(centre, size);
+    }
+
+    /// Move so that the cell at `position` is in the middle of a `size` dot view
+    pub fn centre_on(&mut self, position: Position, (width, height): (usize, usize)) {
+        let half_width = (width * self.scale / 2) as isize;
+        let half_height = (height * self.scale / 2) as isize;
+        self.origin = Position(position.x() - half_width, position.y() - half_height);
+    }
+
+    /// The cell in the middle of a `size` dot view
+    pub fn centre(&self, (width, height): (usize, usize)) -> Position {
+        let half_width = (width * self.scale / 2) as isize;
+        let half_height = (height * self.scale / 2) as isize;
+        self.origin + Position(half_width, half_height)
+    }
+}
+
+impl Default for Viewport {
+    /// The top-left cell of the grid in the top-left corner, with one character per cell
+    fn default() -> Self {
+        Self {
+            origin: Position(0, 0),
+            scale: 1,
+        }
+    }
+}
+
+impl Glyphs {
+    /// Width and height of the dots in each character
+    pub fn dots_per_char(&self) -> (usize, usize) {
+        match self {
+            Self::Block => (1, 1),
+            Self::Half => (1, 2),
+            Self::Braille => (2, 4),
+        }
+    }
+
+    /// Which bit of a [`Shade::Dots`] is for the dot at `(x, y)` within a character
+    fn dot_bit(&self, (x, y): (usize, usize)) -> u8 {
+        match self {
+            Self::Block | Self::Half => 1 << y,
+            // the first three rows of each column are numbered downwards, then the fourth row is
+            // numbered across, matching the order of the dots in the Unicode braille patterns
+            Self::Braille if y < 3 => 1 << (y + x * 3),
+            Self::Braille => 1 << (6 + x),
+        }
+    }
+
+    /// The character to draw with the `dots` set, where none of them are set
+    fn char(&self, dots: u8) -> char {
+        match self {
+            Self::Block if dots == 0 => ' ',
+            Self::Block => '•',
+            Self::Half => [' ', '▀', '▄', '█'][dots as usize],
+            Self::Braille => std::char::from_u32(0x2800 + dots as u32)
+                .expect("Expected all 256 braille patterns to be valid chars"),
+        }
+    }
+}
+
+impl FromStr for Glyphs {
+    type Err = ParseGlyphsError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "block" => Ok(Self::Block),
+            "half" => Ok(Self::Half),
+            "braille" => Ok(Self::Braille),
+            _ => Err(ParseGlyphsError),
+        }
+    }
+}
+
+impl error::Error for ParseGlyphsError {}
+
+impl fmt::Display for ParseGlyphsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected one of: block, half, braille")
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.max_speed {
+            f.write_str("max")?;
+        } else {
+            write!(f, "{} gen / {} ms", self.step, self.period.as_millis())?;
+        }
+        if self.cursor.is_some() {
+            f.write_str("    Editing")?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, "    {}", message)?;
+        }
+        Ok(())
+    }
 }
 
 impl TerminalDisplay {
-    pub fn new() -> Result<Self> {
+    pub fn new(glyphs: Glyphs) -> Result<Self> {
         let mut out = io::stdout();
         terminal::enable_raw_mode()?;
-        execute!(out, cursor::Hide)?;
-        Ok(Self { prev_gen: None })
+        execute!(out, cursor::Hide, EnableMouseCapture)?;
+        Ok(Self {
+            glyphs,
+            prev_frame: None,
+            out: Output {
+                stdout: out,
+                recording: None,
+            },
+        })
+    }
+
+    /// Also record everything which is drawn from now on, each time it's drawn
+    pub fn with_recording(
+        mut self,
+        mut recorder: asciicast::Recorder<BufWriter<File>>,
+    ) -> Result<Self> {
+        // the cursor was hidden before there was anything to record it
+        execute!(recorder, cursor::Hide)?;
+        self.out.recording = Some(recorder);
+        Ok(self)
     }
 }
 
 impl Drop for TerminalDisplay {
     fn drop(&mut self) {
-        let mut out = io::stdout();
         // ignore results, don't really care if cleanup fails
-        let _ignored = execute!(out, cursor::Show);
+        let _ignored = execute!(self.out, DisableMouseCapture, cursor::Show);
         let _ignored = terminal::disable_raw_mode();
     }
 }
 
+impl<D, W: Write> GifDisplay<D, W> {
+    pub fn new(display: D, encoder: gif::Encoder<W>, delay: Duration) -> Self {
+        Self {
+            display,
+            encoder,
+            delay,
+            prev_frame: None,
+        }
+    }
+}
+
+impl<E: error::Error + 'static> error::Error for RecordingError<E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Display(err) => Some(err),
+            Self::Io(err) => Some(err),
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for RecordingError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Display(err) => fmt::Display::fmt(err, f),
+            Self::Io(err) => write!(f, "couldn't record: {}", err),
+        }
+    }
+}
+
+impl<E> From<io::Error> for RecordingError<E> {
+    fn from(source: io::Error) -> Self {
+        Self::Io(source)
+    }
+}
+
 impl TerminalDisplay {
     const CELL_OFFSET_X: u16 = Self::BORDER_THICKNESS;
     const CELL_OFFSET_Y: u16 = Self::BORDER_THICKNESS + Self::TITLE_POSITION_Y + 1;
@@ -48,13 +361,20 @@ impl TerminalDisplay {
     const TITLE_POSITION_X: u16 = Self::BORDER_THICKNESS;
     const TITLE_POSITION_Y: u16 = Self::BORDER_THICKNESS;
     const TITLE_TEXT_PREFIX: &'static str = "Generation: ";
+    const TITLE_TEXT_SEPARATOR: &'static str = "    Speed: ";
 
-    /// - if `curr_gen` is `Some` => redraw the title for `next_gen` only if it differs from `curr_gen`
-    /// - if `curr_gen` is `None` => unconditionally redraw the title for `next_gen`
+    const STATUS_LINE_POSITION_X: u16 = Self::BORDER_THICKNESS;
+    const STATUS_LINE_HEIGHT: u16 = 1;
+
+    /// - if `curr_title` is `Some` => redraw the title for `next_title` only if it differs
+    /// - if `curr_title` is `None` => unconditionally redraw the title for `next_title`
+    ///
+    /// Anything past `width` characters is cut off, e.g. a long [`Status::message`]
     fn redraw_title_if_needed(
         &mut self,
-        next_gen: &Generation,
-        curr_gen: Option<&Generation>,
+        next_title: (usize, &Status),
+        curr_title: Option<(usize, &Status)>,
+        width: usize,
     ) -> crossterm::Result<()> {
         enum RedrawStrategy {
             /// Redraw full title (inc. prefix)
@@ -65,27 +385,33 @@ impl TerminalDisplay {
             Nop,
         }
 
-        let next_index = next_gen.index();
-        let strategy = match curr_gen {
-            Some(curr_gen) if next_index == curr_gen.index() => RedrawStrategy::Nop,
+        let strategy = match curr_title {
+            Some(curr_title) if next_title == curr_title => RedrawStrategy::Nop,
             Some(_) => RedrawStrategy::Partial,
             None => RedrawStrategy::Full,
         };
+        let (next_index, next_status) = next_title;
+        let index_len = Self::TITLE_TEXT_PREFIX.len() + next_index.to_string().len();
+        let speed: String = format!("{}{}", Self::TITLE_TEXT_SEPARATOR, next_status)
+            .chars()
+            .take(width.saturating_sub(index_len))
+            .collect();
 
         match strategy {
             RedrawStrategy::Full => {
-                let mut out = io::stdout();
+                let out = &mut self.out;
                 queue!(
                     out,
                     MoveTo(Self::TITLE_POSITION_X, Self::TITLE_POSITION_Y),
                     Clear(ClearType::UntilNewLine),
                     PrintStyledContent(
                         format!("{}{}", Self::TITLE_TEXT_PREFIX, next_index).underlined()
-                    )
+                    ),
+                    Print(&speed)
                 )?;
             }
             RedrawStrategy::Partial => {
-                let mut out = io::stdout();
+                let out = &mut self.out;
                 queue!(
                     out,
                     MoveTo(
@@ -93,7 +419,8 @@ impl TerminalDisplay {
                         Self::TITLE_POSITION_Y,
                     ),
                     Clear(ClearType::UntilNewLine),
-                    PrintStyledContent(next_index.to_string().underlined())
+                    PrintStyledContent(next_index.to_string().underlined()),
+                    Print(&speed)
                 )?;
             }
             RedrawStrategy::Nop => {}
@@ -101,51 +428,224 @@ impl TerminalDisplay {
         Ok(())
     }
 
-    /// - if `curr_gen` is `Some`, redraw those cells of `next_gen` which differ from those of `curr_gen`
-    /// - if `curr_gen` is `None`, unconditionally redraw all the cells of `next_gen`
-    fn redraw_changed_cells(
+    /// - if `curr_line` is `Some` => redraw the `next_line` only if it differs
+    /// - if `curr_line` is `None` => unconditionally redraw the `next_line`
+    fn redraw_status_line_if_needed(
         &mut self,
-        next_gen: &Generation,
-        curr_gen: Option<&Generation>,
+        next_line: &str,
+        curr_line: Option<&str>,
+        (width, height): (usize, usize),
+    ) -> crossterm::Result<()> {
+        if curr_line == Some(next_line) {
+            return Ok(());
+        }
+        let out = &mut self.out;
+        let line: String = next_line.chars().take(width).collect();
+        queue!(
+            out,
+            MoveTo(
+                Self::STATUS_LINE_POSITION_X,
+                Self::CELL_OFFSET_Y + height as u16
+            ),
+            Clear(ClearType::UntilNewLine),
+            Print(line)
+        )?;
+        Ok(())
+    }
+
+    /// - if `curr_frame` is `Some`, redraw those characters of `next_frame` which differ from those
+    ///   of `curr_frame`
+    /// - if `curr_frame` is `None`, unconditionally redraw all the characters of `next_frame`
+    fn redraw_changed_shades(
+        &mut self,
+        next_frame: &Frame,
+        curr_frame: Option<&Frame>,
     ) -> crossterm::Result<()> {
-        for y in 0..next_gen.height() as u16 {
-            for x in 0..next_gen.width() as u16 {
-                let position = Position::from((x, y));
-                let cell_redraw_needed = match curr_gen {
-                    Some(curr_gen) => next_gen[position] != curr_gen[position],
-                    None => true,
-                };
-                if cell_redraw_needed {
-                    self.redraw_cell((x, y), &next_gen[position])?;
+        let (width, _) = next_frame.size;
+        for (idx, &shade) in next_frame.shades.iter().enumerate() {
+            let highlighted = next_frame.cursor_idx == Some(idx);
+            let redraw_needed = match curr_frame {
+                Some(curr_frame) => {
+                    shade != curr_frame.shades[idx]
+                        || highlighted != (curr_frame.cursor_idx == Some(idx))
                 }
+                None => true,
+            };
+            if redraw_needed {
+                let position = ((idx % width) as u16, (idx / width) as u16);
+                self.redraw_shade(position, shade, highlighted)?;
             }
         }
         Ok(())
     }
 
-    fn redraw_cell(&mut self, (x, y): (u16, u16), cell: &Cell) -> crossterm::Result<()> {
-        let cell_display = match cell {
-            Cell::Alive => '•'.bold().dark_green().on_black(),
-            Cell::Dead => ' '.on_black(),
+    fn redraw_shade(
+        &mut self,
+        (x, y): (u16, u16),
+        shade: Shade,
+        highlighted: bool,
+    ) -> crossterm::Result<()> {
+        let shade_display = match (shade, highlighted) {
+            (Shade::Dots(0), false) => ' '.on_black(),
+            (Shade::Dots(dots), false) => self.glyphs.char(dots).bold().dark_green().on_black(),
+            (Shade::OffGrid, false) => ' '.reset(),
+            (Shade::Dots(dots), true) => self.glyphs.char(dots).bold().green().on_dark_grey(),
+            (Shade::OffGrid, true) => ' '.on_dark_grey(),
         };
-        let mut out = io::stdout();
+        let out = &mut self.out;
         queue!(
             out,
             MoveTo(x + Self::CELL_OFFSET_X, y + Self::CELL_OFFSET_Y),
-            PrintStyledContent(cell_display),
+            PrintStyledContent(shade_display),
         )?;
         Ok(())
     }
 }
 
-impl Display<Event, crossterm::ErrorKind> for TerminalDisplay {
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.stdout.write(buf)?;
+        if let Some(recording) = &mut self.recording {
+            recording.write_all(&buf[..len])?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()?;
+        if let Some(recording) = &mut self.recording {
+            recording.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Frame {
+    /// The part of `gen` within the `viewport`, when it's `size` characters of `glyphs` big
+    fn new(
+        gen: &Generation,
+        viewport: &Viewport,
+        status: &Status,
+        glyphs: Glyphs,
+        size: (usize, usize),
+    ) -> Self {
+        let (width, height) = size;
+        let (dots_x, dots_y) = glyphs.dots_per_char();
+        let scale = viewport.scale() as isize;
+        // width and height of the cells drawn as each character
+        let (char_width, char_height) = (dots_x as isize * scale, dots_y as isize * scale);
+        let min = viewport.origin();
+        let max = min + Position(width as isize * char_width, height as isize * char_height);
+
+        let mut shades = Vec::with_capacity(width * height);
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let block = min + Position(x * char_width, y * char_height);
+                let off_grid = gen.topology() != Topology::Unbounded
+                    && (block.x() + char_width <= 0
+                        || block.y() + char_height <= 0
+                        || block.x() >= gen.width() as isize
+                        || block.y() >= gen.height() as isize);
+                shades.push(if off_grid {
+                    Shade::OffGrid
+                } else {
+                    Shade::Dots(0)
+                });
+            }
+        }
+        for position in gen.live_cells(Some((min, max))) {
+            let x = ((position.x() - min.x()) / scale) as usize;
+            let y = ((position.y() - min.y()) / scale) as usize;
+            let dot_bit = glyphs.dot_bit((x % dots_x, y % dots_y));
+            if let Shade::Dots(dots) = &mut shades[x / dots_x + y / dots_y * width] {
+                *dots |= dot_bit;
+            }
+        }
+        let cursor_idx = status.cursor.and_then(|cursor| {
+            let x = (cursor.x() - min.x()).div_euclid(char_width);
+            let y = (cursor.y() - min.y()).div_euclid(char_height);
+            let on_screen = (0..width as isize).contains(&x) && (0..height as isize).contains(&y);
+            on_screen.then(|| x as usize + y as usize * width)
+        });
+        Self {
+            index: gen.index(),
+            status: status.clone(),
+            size,
+            shades,
+            cursor_idx,
+            status_line: Self::status_line(gen, status),
+        }
+    }
+
+    fn status_line(gen: &Generation, status: &Status) -> String {
+        let population = gen.population();
+        let bounds = gen.bounds();
+        // an unbounded generation has no area of its own, so go by the area of its live cells,
+        // which can spread out too far for their area to fit in an integer
+        let area = match (gen.topology(), bounds) {
+            (Topology::Unbounded, Some((min, max))) => {
+                (max.x() - min.x()) as f64 * (max.y() - min.y()) as f64
+            }
+            (Topology::Unbounded, None) => 0.0,
+            _ => (gen.width() * gen.height()) as f64,
+        };
+        let density = if area != 0.0 {
+            population as f64 / area
+        } else {
+            0.0
+        };
+
+        // births and deaths as +n -n, to keep the whole line short enough to fit
+        let mut line = format!(
+            "Population: {} ({:.1}%)  +{} -{}",
+            population,
+            density * 100.0,
+            gen.births(),
+            gen.deaths()
+        );
+        if let Some((min, max)) = bounds {
+            line += &format!(
+                "  Bounds: {}x{} at {},{}",
+                max.x() - min.x(),
+                max.y() - min.y(),
+                min.x(),
+                min.y()
+            );
+        }
+        let rate = status
+            .rate
+            .map_or("-".to_string(), |rate| format!("{:.1}", rate));
+        let target_rate = if status.max_speed || status.period.as_millis() == 0 {
+            "max".to_string()
+        } else {
+            format!("{:.1}", status.step as f64 / status.period.as_secs_f64())
+        };
+        line += &format!("  Gen/s: {} of {}", rate, target_rate);
+        if let Some((index, period)) = status.stable {
+            line = format!(
+                "Stabilised at generation {} with period {}  {}",
+                index, period, line
+            );
+        }
+        line
+    }
+}
+
+impl Display for TerminalDisplay {
+    type Event = Event;
+    type Error = crossterm::ErrorKind;
+
     fn available_cells(&self) -> Option<(usize, usize)> {
         let (term_width, term_height) = terminal::size().ok()?;
         let (avail_width, avail_height) = (
             term_width - Self::CELL_OFFSET_X - Self::BORDER_THICKNESS,
-            term_height - Self::CELL_OFFSET_Y - Self::BORDER_THICKNESS,
+            term_height - Self::CELL_OFFSET_Y - Self::STATUS_LINE_HEIGHT - Self::BORDER_THICKNESS,
         );
-        Some((avail_width as usize, avail_height as usize))
+        let (dots_x, dots_y) = self.glyphs.dots_per_char();
+        Some((
+            avail_width as usize * dots_x,
+            avail_height as usize * dots_y,
+        ))
     }
 
     fn take_pending_event(&self) -> Result<Option<Event>> {
@@ -157,34 +657,170 @@ impl Display<Event, crossterm::ErrorKind> for TerminalDisplay {
         }
     }
 
-    fn draw(&mut self, next_gen: &Generation) -> crossterm::Result<()> {
-        let mut curr_gen = Some(next_gen.clone());
-        mem::swap(&mut curr_gen, &mut self.prev_gen);
+    fn cells_at(
+        &self,
+        (column, row): (u16, u16),
+        viewport: &Viewport,
+    ) -> Option<(Position, Position)> {
+        let (dots_x, dots_y) = self.glyphs.dots_per_char();
+        let (avail_width, avail_height) = self.available_cells()?;
+        let x = column.checked_sub(Self::CELL_OFFSET_X)? as usize;
+        let y = row.checked_sub(Self::CELL_OFFSET_Y)? as usize;
+        // anywhere on the border or status line is outside of the frame the cells are drawn in
+        if x >= avail_width / dots_x || y >= avail_height / dots_y {
+            return None;
+        }
+        // the terminal only says which character was clicked, not where within it, so settle for
+        // its top left dot, rather than every cell the character covers
+        let scale = viewport.scale() as isize;
+        let dot = Position((x * dots_x) as isize, (y * dots_y) as isize);
+        let min = viewport.origin() + Position(dot.x() * scale, dot.y() * scale);
+        Some((min, min + Position(scale, scale)))
+    }
 
-        let curr_gen = curr_gen.as_ref();
-        let (width, height) = (next_gen.width() as u16, next_gen.height() as u16);
+    fn draw(
+        &mut self,
+        next_gen: &Generation,
+        viewport: &Viewport,
+        status: &Status,
+    ) -> crossterm::Result<()> {
+        let (width, height) = self
+            .available_cells()
+            .unwrap_or((next_gen.width(), next_gen.height()));
+        let (dots_x, dots_y) = self.glyphs.dots_per_char();
+        let size = (width.div_ceil(dots_x), height.div_ceil(dots_y));
+        let next_frame = Frame::new(next_gen, viewport, status, self.glyphs, size);
 
         // we can get away with a partial redraw if
         //     1. not specifically asked to redraw everything from scratch (e.g. on the first draw)
-        //     2. the next_gen is the same size as the curr_gen (and we actually have a curr_gen)
-        let full_redraw_needed = match curr_gen {
-            Some(curr_gen) => {
-                width as usize != curr_gen.width() || height as usize != curr_gen.height()
+        //     2. the next_frame is the same size as the curr_frame (and we actually have one)
+        let curr_frame = self
+            .prev_frame
+            .take()
+            .filter(|curr_frame| curr_frame.size == next_frame.size);
+
+        match &curr_frame {
+            Some(curr_frame) => {
+                self.redraw_title_if_needed(
+                    (next_frame.index, &next_frame.status),
+                    Some((curr_frame.index, &curr_frame.status)),
+                    next_frame.size.0,
+                )?;
+                self.redraw_changed_shades(&next_frame, Some(curr_frame))?;
+                self.redraw_status_line_if_needed(
+                    &next_frame.status_line,
+                    Some(&curr_frame.status_line),
+                    next_frame.size,
+                )?;
             }
-            None => true,
-        };
+            None => {
+                queue!(self.out, Clear(ClearType::All))?;
+                self.redraw_title_if_needed(
+                    (next_frame.index, &next_frame.status),
+                    None,
+                    next_frame.size.0,
+                )?;
+                self.redraw_changed_shades(&next_frame, None)?;
+                self.redraw_status_line_if_needed(&next_frame.status_line, None, next_frame.size)?;
+            }
+        }
+        self.prev_frame = Some(next_frame);
 
-        let mut out = io::stdout();
-        if full_redraw_needed {
-            queue!(out, Clear(ClearType::All))?;
-            self.redraw_title_if_needed(next_gen, None)?;
-            self.redraw_changed_cells(next_gen, None)?;
-        } else {
-            self.redraw_title_if_needed(next_gen, curr_gen)?;
-            self.redraw_changed_cells(next_gen, curr_gen)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+impl Display for HeadlessDisplay {
+    type Event = Infallible;
+    type Error = Infallible;
+
+    fn available_cells(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    fn take_pending_event(&self) -> std::result::Result<Option<Infallible>, Infallible> {
+        Ok(None)
+    }
+
+    fn cells_at(&self, _at: (u16, u16), _viewport: &Viewport) -> Option<(Position, Position)> {
+        None
+    }
+
+    fn draw(
+        &mut self,
+        _gen: &Generation,
+        _viewport: &Viewport,
+        _status: &Status,
+    ) -> std::result::Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+impl<D: Display, W: Write> Display for GifDisplay<D, W> {
+    type Event = D::Event;
+    type Error = RecordingError<D::Error>;
+
+    fn available_cells(&self) -> Option<(usize, usize)> {
+        self.display.available_cells()
+    }
+
+    fn take_pending_event(&self) -> std::result::Result<Option<D::Event>, Self::Error> {
+        self.display
+            .take_pending_event()
+            .map_err(RecordingError::Display)
+    }
+
+    fn cells_at(&self, at: (u16, u16), viewport: &Viewport) -> Option<(Position, Position)> {
+        self.display.cells_at(at, viewport)
+    }
+
+    fn draw(
+        &mut self,
+        gen: &Generation,
+        viewport: &Viewport,
+        status: &Status,
+    ) -> std::result::Result<(), Self::Error> {
+        self.display
+            .draw(gen, viewport, status)
+            .map_err(RecordingError::Display)?;
+
+        let mut hasher = DefaultHasher::new();
+        gen.hash(&mut hasher);
+        let frame = (gen.index(), hasher.finish());
+        if self.prev_frame != Some(frame) {
+            self.encoder.write_frame(gen, self.delay)?;
+            self.prev_frame = Some(frame);
         }
+        Ok(())
+    }
 
-        out.flush()?;
+    fn finish(&mut self) -> std::result::Result<(), Self::Error> {
+        self.display.finish().map_err(RecordingError::Display)?;
+        self.encoder.finish()?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_lines_cope_with_live_cells_spread_too_far_apart_to_have_an_area() {
+        let far = Universe::MAX_SEED_DISTANCE;
+        let cells = [Position(-far, -far), Position(far, far)];
+        let gen = Generation::from_universe(8, 8, Universe::new(0, Rule::CONWAY, &cells));
+        let status = Status {
+            period: Duration::ZERO,
+            step: 1,
+            max_speed: false,
+            cursor: None,
+            rate: None,
+            stable: None,
+            message: None,
+        };
+        let line = Frame::status_line(&gen, &status);
+        assert!(line.starts_with("Population: 2 (0.0%)"), "{}", line);
+    }
+}
@@ -0,0 +1,215 @@
+//! Images of generations, e.g. to illustrate documentation with
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::{error, fmt};
+
+use crate::game::*;
+
+pub mod asciicast;
+pub mod gif;
+pub mod netpbm;
+pub mod png;
+pub mod svg;
+
+/// What the cells of an image look like
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Theme {
+    /// Width (and height) of each cell, in pixels, including its share of any grid lines
+    pub cell_size: usize,
+    pub alive: Colour,
+    pub dead: Colour,
+    /// Of the 1 pixel wide lines around each cell, if there should be any
+    pub grid: Option<Colour>,
+    /// Write the index of the generation below it, in the colour of live cells. Only SVG images
+    /// can have text in them, so other formats leave it out.
+    pub caption: bool,
+}
+
+/// 24-bit RGB, parsed from and displayed as hex like `#ff8000`
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Colour(pub u8, pub u8, pub u8);
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseColourError;
+
+/// Format of a still image of a single generation
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ImageFormat {
+    /// `.png`
+    Png,
+    /// `.ppm`, in colour
+    Ppm,
+    /// `.pbm`, in black and white
+    Pbm,
+    /// `.svg`
+    Svg,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseImageFormatError;
+
+/// Index of each colour in the palette of an image
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[repr(u8)]
+enum Paint {
+    Dead = 0,
+    Alive = 1,
+    Grid = 2,
+}
+
+/// Write an image of the whole grid of `generation` to a file in the given format, replacing the
+/// file if it already exists
+pub fn write(
+    path: &Path,
+    format: ImageFormat,
+    generation: &Generation,
+    theme: &Theme,
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    format.write(generation, theme, &mut out)?;
+    out.flush()
+}
+
+impl Theme {
+    /// Width and height, in pixels, of an image of the whole grid of `generation`
+    pub fn image_size(&self, generation: &Generation) -> (usize, usize) {
+        // each cell only has the lines above and to the left of it, so there's one more of each
+        let border = self.grid.is_some() as usize;
+        (
+            generation.width() * self.cell_size + border,
+            generation.height() * self.cell_size + border,
+        )
+    }
+
+    /// The colours which the pixels of an image can be, in order of [`Paint`]
+    fn palette(&self) -> Vec<Colour> {
+        let mut palette = vec![self.dead, self.alive];
+        palette.extend(self.grid);
+        palette
+    }
+
+    /// The [`Paint`] of each pixel of an image of the whole grid of `generation`, row by row.
+    /// Unbounded generations don't have a whole grid, so any cells outside of it are left out.
+    fn paint(&self, generation: &Generation) -> Vec<u8> {
+        let (width, height) = self.image_size(generation);
+        let mut pixels = vec![Paint::Dead as u8; width * height];
+        if self.grid.is_some() {
+            for (y, row) in pixels.chunks_mut(width).enumerate() {
+                if y % self.cell_size == 0 {
+                    row.fill(Paint::Grid as u8);
+                } else {
+                    row.iter_mut()
+                        .step_by(self.cell_size)
+                        .for_each(|pixel| *pixel = Paint::Grid as u8);
+                }
+            }
+        }
+
+        // the first row and column of each cell is taken up by any grid lines
+        let border = self.grid.is_some() as usize;
+        let inner_size = self.cell_size - border;
+        let grid = (
+            Position(0, 0),
+            Position::from((generation.width(), generation.height())),
+        );
+        for position in generation.live_cells(Some(grid)) {
+            let (x, y) = (position.x() as usize, position.y() as usize);
+            for row in 0..inner_size {
+                let start =
+                    (y * self.cell_size + border + row) * width + x * self.cell_size + border;
+                pixels[start..start + inner_size].fill(Paint::Alive as u8);
+            }
+        }
+        pixels
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            cell_size: 4,
+            alive: Colour(0xff, 0xff, 0xff),
+            dead: Colour(0x00, 0x00, 0x00),
+            grid: None,
+            caption: false,
+        }
+    }
+}
+
+impl FromStr for Colour {
+    type Err = ParseColourError;
+
+    /// e.g. `#ff8000`, where the `'#'` is optional
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return Err(ParseColourError);
+        }
+        let channel = |idx: usize| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap();
+        Ok(Self(channel(0), channel(2), channel(4)))
+    }
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+impl error::Error for ParseColourError {}
+
+impl fmt::Display for ParseColourError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected a hex colour like #ff8000")
+    }
+}
+
+impl ImageFormat {
+    pub fn write<W: Write>(
+        self,
+        generation: &Generation,
+        theme: &Theme,
+        out: &mut W,
+    ) -> io::Result<()> {
+        match self {
+            Self::Png => png::write(generation, theme, out),
+            Self::Ppm => netpbm::write_ppm(generation, theme, out),
+            Self::Pbm => netpbm::write_pbm(generation, theme, out),
+            Self::Svg => svg::write(generation, theme, out),
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Ppm => "ppm",
+            Self::Pbm => "pbm",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = ParseImageFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "png" => Ok(Self::Png),
+            "ppm" => Ok(Self::Ppm),
+            "pbm" => Ok(Self::Pbm),
+            "svg" => Ok(Self::Svg),
+            _ => Err(ParseImageFormatError),
+        }
+    }
+}
+
+impl error::Error for ParseImageFormatError {}
+
+impl fmt::Display for ParseImageFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected one of: png, ppm, pbm, svg")
+    }
+}
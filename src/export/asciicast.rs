@@ -0,0 +1,121 @@
+//! [asciicast v2](https://docs.asciinema.org/manual/asciicast/v2/) recordings of everything written
+//! to a terminal, which can be replayed with asciinema
+
+use std::io::{self, Write};
+use std::str;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Buffers everything written to it, then records it as a single output event when flushed, timed
+/// from when the recording started
+pub struct Recorder<W: Write> {
+    out: W,
+    start: Instant,
+    /// Written since the last flush
+    pending: Vec<u8>,
+}
+
+impl<W: Write> Recorder<W> {
+    /// Writes the header, for a terminal which is `width` columns by `height` rows big
+    pub fn new(mut out: W, (width, height): (u16, u16)) -> io::Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs());
+        writeln!(
+            out,
+            r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {}}}"#,
+            width, height, timestamp
+        )?;
+        Ok(Self {
+            out,
+            start: Instant::now(),
+            pending: Vec::new(),
+        })
+    }
+
+    /// Everything pending up to the first invalid UTF-8 sequence, keeping back any partial
+    /// character at the end until the rest of it is written
+    fn take_pending_text(&mut self) -> String {
+        let valid_len = match str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            // there's nothing else that could be done with bytes which aren't text anyway
+            Err(_) => self.pending.len(),
+        };
+        let rest = self.pending.split_off(valid_len);
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending = rest;
+        text
+    }
+}
+
+impl<W: Write> Write for Recorder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let text = self.take_pending_text();
+        if !text.is_empty() {
+            let time = self.start.elapsed().as_secs_f64();
+            writeln!(self.out, r#"[{:.6}, "o", {}]"#, time, json_string(&text))?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Quoted, with anything which can't appear in a JSON string as it is escaped
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            ch if ch.is_control() => json.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => json.push(ch),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_anything_which_cant_appear_in_a_json_string() {
+        assert_eq!(
+            json_string("\x1b[2J\"quoted\" \\ line\nnext\r\ttab"),
+            r#""\u001b[2J\"quoted\" \\ line\nnext\r\ttab""#
+        );
+        assert_eq!(json_string("█ ⣿"), "\"█ ⣿\"");
+    }
+
+    #[test]
+    fn holds_back_a_character_split_across_writes_until_it_is_whole() {
+        let mut out = Vec::new();
+        let mut recorder = Recorder::new(&mut out, (80, 24)).unwrap();
+        let block = "█".as_bytes();
+        recorder.write_all(b"a").unwrap();
+        recorder.write_all(&block[..1]).unwrap();
+        recorder.flush().unwrap();
+        recorder.write_all(&block[1..]).unwrap();
+        recorder.flush().unwrap();
+        // nothing was pending, so nothing is recorded
+        recorder.flush().unwrap();
+        drop(recorder);
+
+        let out = String::from_utf8(out).unwrap();
+        let events = out
+            .lines()
+            .skip(1)
+            .map(|line| line.split_once(", ").unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(events, [r#""o", "a"]"#, r#""o", "█"]"#]);
+    }
+}
@@ -0,0 +1,344 @@
+//! Animated [GIF](https://www.w3.org/Graphics/GIF/spec-gif89a.txt) images, with one frame per
+//! generation which loop forever
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use super::*;
+
+const HEADER: &[u8] = b"GIF89a";
+const TRAILER: u8 = 0x3b;
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const IMAGE_SEPARATOR: u8 = 0x2c;
+/// Codes are never wider than this, so there are at most `2^12` of them
+const MAX_CODE_SIZE: u8 = 12;
+
+/// Writes each generation it's given as the next frame of the animation, and finishes it off once
+/// dropped if it wasn't already finished
+pub struct Encoder<W: Write> {
+    out: W,
+    theme: Theme,
+    /// In pixels, once the header has been written with the size of the first frame
+    size: Option<(u16, u16)>,
+    finished: bool,
+}
+
+/// Packs variable-width codes into bytes, least significant bit first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Bits which don't fill a whole byte yet
+    pending: u32,
+    pending_len: u8,
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn new(out: W, theme: Theme) -> Self {
+        Self {
+            out,
+            theme,
+            size: None,
+            finished: false,
+        }
+    }
+
+    /// Every frame shows the whole grid, so every generation should be the same size as the first
+    pub fn write_frame(&mut self, generation: &Generation, delay: Duration) -> io::Result<()> {
+        let (width, height) = match self.size {
+            Some(size) => size,
+            None => self.write_header(generation)?,
+        };
+
+        // graphic control extension, for the delay
+        let centis = (delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+        self.out.write_all(&[EXTENSION_INTRODUCER, 0xf9, 4, 0])?;
+        self.out.write_all(&centis.to_le_bytes())?;
+        self.out.write_all(&[0, 0])?;
+
+        self.out.write_all(&[IMAGE_SEPARATOR])?;
+        self.out.write_all(&[0, 0, 0, 0])?;
+        self.out.write_all(&width.to_le_bytes())?;
+        self.out.write_all(&height.to_le_bytes())?;
+        self.out.write_all(&[0])?;
+
+        let min_code_size = Self::min_code_size(self.theme.palette().len());
+        let pixels = self.theme.paint(generation);
+        self.out.write_all(&[min_code_size])?;
+        for block in compress(min_code_size, &pixels).chunks(u8::MAX as usize) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0])
+    }
+
+    /// Write the trailer, after which no more frames can be written
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.finished {
+            self.finished = true;
+            if self.size.is_some() {
+                self.out.write_all(&[TRAILER])?;
+            }
+        }
+        self.out.flush()
+    }
+
+    /// Logical screen descriptor, global colour table and looping extension, returning the size of
+    /// the screen
+    fn write_header(&mut self, generation: &Generation) -> io::Result<(u16, u16)> {
+        let (width, height) = self.theme.image_size(generation);
+        let too_big = || io::Error::new(io::ErrorKind::InvalidInput, "too big for a GIF image");
+        let width = u16::try_from(width).map_err(|_| too_big())?;
+        let height = u16::try_from(height).map_err(|_| too_big())?;
+
+        let palette = self.theme.palette();
+        // the colour table has `2^(n + 1)` entries, where `n` is in the bottom 3 bits of the flags
+        let table_len = palette.len().next_power_of_two().max(2);
+        let table_bits = table_len.trailing_zeros() as u8 - 1;
+
+        self.out.write_all(HEADER)?;
+        self.out.write_all(&width.to_le_bytes())?;
+        self.out.write_all(&height.to_le_bytes())?;
+        self.out.write_all(&[0x80 | table_bits, 0, 0])?;
+        for idx in 0..table_len {
+            let Colour(r, g, b) = palette.get(idx).copied().unwrap_or(self.theme.dead);
+            self.out.write_all(&[r, g, b])?;
+        }
+
+        // loop forever, see http://www.vurdalakov.net/misc/gif/netscape-looping-application-extension
+        self.out.write_all(&[EXTENSION_INTRODUCER, 0xff, 11])?;
+        self.out.write_all(b"NETSCAPE2.0")?;
+        self.out.write_all(&[3, 1, 0, 0, 0])?;
+
+        self.size = Some((width, height));
+        Ok((width, height))
+    }
+
+    /// Codes are at least 2 bits wide, even if there are only 2 colours
+    fn min_code_size(palette_len: usize) -> u8 {
+        (palette_len.next_power_of_two().trailing_zeros() as u8).max(2)
+    }
+}
+
+impl<W: Write> Drop for Encoder<W> {
+    fn drop(&mut self) {
+        // only in case it wasn't finished already, e.g. after an error, so there's no way to report
+        // the result from here
+        let _ignored = self.finish();
+    }
+}
+
+/// LZW compression of the palette `indices` of some pixels, as the variable-width codes used by
+/// GIF, starting with a clear code and resetting the table of codes whenever it fills up
+fn compress(min_code_size: u8, indices: &[u8]) -> Vec<u8> {
+    let clear_code = 1u16 << min_code_size;
+    let end_code = clear_code + 1;
+    let mut code_size = min_code_size + 1;
+    let mut next_code = end_code + 1;
+    // the code for each known run of indices, by the code for all but the last index, and the last
+    let mut codes = HashMap::<(u16, u8), u16>::new();
+
+    let mut bits = BitWriter::default();
+    bits.write(clear_code, code_size);
+    let mut prefix: Option<u16> = None;
+    for &idx in indices {
+        let prefix_code = match prefix {
+            Some(prefix_code) => prefix_code,
+            None => {
+                prefix = Some(idx as u16);
+                continue;
+            }
+        };
+        if let Some(&code) = codes.get(&(prefix_code, idx)) {
+            prefix = Some(code);
+            continue;
+        }
+        bits.write(prefix_code, code_size);
+        codes.insert((prefix_code, idx), next_code);
+        if next_code == 1 << code_size {
+            code_size += 1;
+        }
+        next_code += 1;
+        if next_code == 1 << MAX_CODE_SIZE {
+            bits.write(clear_code, code_size);
+            codes.clear();
+            code_size = min_code_size + 1;
+            next_code = end_code + 1;
+        }
+        prefix = Some(idx as u16);
+    }
+    if let Some(prefix) = prefix {
+        bits.write(prefix, code_size);
+    }
+    bits.write(end_code, code_size);
+    bits.finish()
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, code_size: u8) {
+        self.pending |= (code as u32) << self.pending_len;
+        self.pending_len += code_size;
+        while self.pending_len >= 8 {
+            self.bytes.push(self.pending as u8);
+            self.pending >>= 8;
+            self.pending_len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.pending_len != 0 {
+            self.bytes.push(self.pending as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The palette indices of the pixels of each frame of a whole `gif`, after checking that every
+    /// frame is as big as the screen
+    fn decode(gif: &[u8]) -> Vec<Vec<u8>> {
+        assert_eq!(&gif[..HEADER.len()], HEADER);
+        let screen = &gif[HEADER.len()..HEADER.len() + 7];
+        let size = (&screen[0..2], &screen[2..4]);
+        let table_len = 2 << (screen[4] & 0x07);
+        let mut rest = &gif[HEADER.len() + 7 + 3 * table_len..];
+
+        let mut frames = Vec::new();
+        loop {
+            match rest[0] {
+                EXTENSION_INTRODUCER => {
+                    let (_, after) = sub_blocks(&rest[2..]);
+                    rest = after;
+                }
+                IMAGE_SEPARATOR => {
+                    assert_eq!((&rest[5..7], &rest[7..9]), size);
+                    let min_code_size = rest[10];
+                    let (data, after) = sub_blocks(&rest[11..]);
+                    frames.push(decompress(min_code_size, &data));
+                    rest = after;
+                }
+                TRAILER => {
+                    assert_eq!(rest.len(), 1);
+                    return frames;
+                }
+                byte => panic!("unexpected block {:#x}", byte),
+            }
+        }
+    }
+
+    /// The data of a run of sub-blocks joined together, and whatever comes after them
+    fn sub_blocks(mut bytes: &[u8]) -> (Vec<u8>, &[u8]) {
+        let mut data = Vec::new();
+        loop {
+            let len = bytes[0] as usize;
+            if len == 0 {
+                return (data, &bytes[1..]);
+            }
+            data.extend_from_slice(&bytes[1..=len]);
+            bytes = &bytes[len + 1..];
+        }
+    }
+
+    /// The reverse of [`compress`], written the way decoders usually are, rather than sharing
+    /// anything with it
+    fn decompress(min_code_size: u8, bytes: &[u8]) -> Vec<u8> {
+        let clear_code = 1usize << min_code_size;
+        let end_code = clear_code + 1;
+        let initial_table = || {
+            let mut table = (0..clear_code)
+                .map(|idx| vec![idx as u8])
+                .collect::<Vec<_>>();
+            table.extend([Vec::new(), Vec::new()]);
+            table
+        };
+        let mut table = initial_table();
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut indices = Vec::new();
+        let mut bit = 0;
+        loop {
+            let code = (0..code_size as usize)
+                .map(|idx| ((bytes[(bit + idx) / 8] >> ((bit + idx) % 8)) & 1) as usize)
+                .enumerate()
+                .fold(0, |code, (idx, value)| code | value << idx);
+            bit += code_size as usize;
+            if code == clear_code {
+                table = initial_table();
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end_code {
+                return indices;
+            }
+            let run = match (table.get(code), &previous) {
+                (Some(run), _) => run.clone(),
+                // the code the encoder has only just made, from the previous run and its own start
+                (None, Some(previous)) => [&previous[..], &previous[..1]].concat(),
+                (None, None) => panic!("code {} isn't in the table", code),
+            };
+            if let Some(previous) = previous {
+                table.push([&previous[..], &run[..1]].concat());
+            }
+            if table.len() == 1 << code_size && code_size < MAX_CODE_SIZE {
+                code_size += 1;
+            }
+            indices.extend_from_slice(&run);
+            previous = Some(run);
+        }
+    }
+
+    #[test]
+    fn compresses_indices_which_decompress_the_same() {
+        for indices in [vec![], vec![1], vec![0, 1, 0, 1, 0, 1, 1, 1, 1, 1, 1, 0]] {
+            assert_eq!(decompress(2, &compress(2, &indices)), indices);
+        }
+    }
+
+    #[test]
+    fn resets_the_table_of_codes_once_it_fills_up() {
+        // a xorshift sequence doesn't repeat itself for long, so fills the table quickly
+        let mut state = 1u32;
+        let indices = (0..100_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                (state % 3) as u8
+            })
+            .collect::<Vec<_>>();
+        let compressed = compress(2, &indices);
+        assert_eq!(decompress(2, &compressed), indices);
+    }
+
+    #[test]
+    fn writes_frames_which_decode_to_the_pixels_of_each_generation() {
+        let theme = Theme {
+            cell_size: 3,
+            grid: Some(Colour(0x80, 0x80, 0x80)),
+            ..Theme::default()
+        };
+        let mut generation = Generation::filled(0, 5, 4, Rule::CONWAY, Cell::Dead).unwrap();
+        for &(x, y) in &[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            generation.set(Position(x, y), Cell::Alive);
+        }
+        let next = Generation::nth_after(&generation, 1);
+
+        let mut gif = Vec::new();
+        let mut encoder = Encoder::new(&mut gif, theme);
+        encoder
+            .write_frame(&generation, Duration::from_millis(100))
+            .unwrap();
+        encoder
+            .write_frame(&next, Duration::from_millis(100))
+            .unwrap();
+        encoder.finish().unwrap();
+        drop(encoder);
+
+        assert_eq!(decode(&gif), [theme.paint(&generation), theme.paint(&next)]);
+    }
+}
@@ -0,0 +1,63 @@
+//! The binary [Netpbm](https://netpbm.sourceforge.net/doc/#formats) formats, which are simple
+//! enough to write by hand and for most image tools to read
+
+use std::io::{self, Write};
+
+use super::*;
+
+/// In the colours of the `theme`
+pub fn write_ppm<W: Write>(generation: &Generation, theme: &Theme, out: &mut W) -> io::Result<()> {
+    let (width, height) = theme.image_size(generation);
+    writeln!(out, "P6")?;
+    writeln!(out, "{} {}", width, height)?;
+    writeln!(out, "255")?;
+    let palette = theme.palette();
+    for paint in theme.paint(generation) {
+        let Colour(r, g, b) = palette[paint as usize];
+        out.write_all(&[r, g, b])?;
+    }
+    Ok(())
+}
+
+/// In black and white whatever the colours of the `theme`, with live cells and any grid lines in
+/// black
+pub fn write_pbm<W: Write>(generation: &Generation, theme: &Theme, out: &mut W) -> io::Result<()> {
+    let (width, height) = theme.image_size(generation);
+    writeln!(out, "P4")?;
+    writeln!(out, "{} {}", width, height)?;
+    // each row is padded to a whole number of bytes, with the leftmost pixel in the highest bit
+    for row in theme.paint(generation).chunks(width) {
+        for pixels in row.chunks(8) {
+            let byte = pixels.iter().enumerate().fold(0u8, |byte, (idx, &paint)| {
+                let black = paint != Paint::Dead as u8;
+                byte | (black as u8) << (7 - idx)
+            });
+            out.write_all(&[byte])?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_a_pbm_with_an_odd_width_byte_for_byte() {
+        let theme = Theme {
+            cell_size: 1,
+            ..Theme::default()
+        };
+        let mut generation = Generation::filled(0, 9, 2, Rule::CONWAY, Cell::Dead).unwrap();
+        for &(x, y) in &[(0, 0), (8, 0), (4, 1)] {
+            generation.set(Position(x, y), Cell::Alive);
+        }
+
+        let mut out = Vec::new();
+        write_pbm(&generation, &theme, &mut out).unwrap();
+        let mut expected = b"P4\n9 2\n".to_vec();
+        // each row takes 2 bytes, with the 9th pixel in the highest bit of the second
+        expected.extend_from_slice(&[0b1000_0000, 0b1000_0000, 0b0000_1000, 0b0000_0000]);
+        assert_eq!(out, expected);
+    }
+}
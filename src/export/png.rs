@@ -0,0 +1,199 @@
+//! [PNG](https://www.w3.org/TR/png/) images with a palette, stored without any compression so that
+//! no deflate implementation is needed
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+
+use super::*;
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const COLOUR_TYPE_PALETTE: u8 = 3;
+/// Largest amount of data in a single stored deflate block
+const MAX_STORED_LEN: usize = u16::MAX as usize;
+
+pub fn write<W: Write>(generation: &Generation, theme: &Theme, out: &mut W) -> io::Result<()> {
+    let (width, height) = theme.image_size(generation);
+    let too_big = || io::Error::new(io::ErrorKind::InvalidInput, "too big for a PNG image");
+    let width32 = u32::try_from(width).map_err(|_| too_big())?;
+    let height32 = u32::try_from(height).map_err(|_| too_big())?;
+
+    let palette = theme.palette();
+    // as few bits per pixel as will fit every colour of the palette
+    let bit_depth = if palette.len() <= 2 { 1 } else { 2 };
+
+    out.write_all(SIGNATURE)?;
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width32.to_be_bytes());
+    header.extend_from_slice(&height32.to_be_bytes());
+    header.extend_from_slice(&[bit_depth, COLOUR_TYPE_PALETTE, 0, 0, 0]);
+    write_chunk(out, b"IHDR", &header)?;
+
+    let colours: Vec<u8> = palette
+        .iter()
+        .flat_map(|&Colour(r, g, b)| vec![r, g, b])
+        .collect();
+    write_chunk(out, b"PLTE", &colours)?;
+
+    // each row starts with the type of filter it's been through, which is none
+    let pixels_per_byte = 8 / bit_depth as usize;
+    let mut scanlines = Vec::new();
+    for row in theme.paint(generation).chunks(width) {
+        scanlines.push(0);
+        for pixels in row.chunks(pixels_per_byte) {
+            let byte = pixels.iter().enumerate().fold(0, |byte, (idx, &paint)| {
+                byte | paint << (8 - bit_depth as usize * (idx + 1))
+            });
+            scanlines.push(byte);
+        }
+    }
+    write_chunk(out, b"IDAT", &zlib_stored(&scanlines))?;
+
+    write_chunk(out, b"IEND", &[])
+}
+
+/// Length, type, data and a checksum of the type and data
+fn write_chunk<W: Write>(out: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too big for a PNG chunk"))?;
+    out.write_all(&len.to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let crc = !crc32(crc32(!0, kind), data);
+    out.write_all(&crc.to_be_bytes())
+}
+
+/// A zlib stream of `data`, in deflate blocks which are stored as they are
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // deflate with a 32K window and no preset dictionary, checked by the header being a multiple
+    // of 31 when read as a big-endian `u16`
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_LEN).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(is_final as u8);
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+/// Continue the CRC-32 `crc` (before its final inversion) with `bytes`
+fn crc32(mut crc: u32, bytes: &[u8]) -> u32 {
+    const POLYNOMIAL: u32 = 0xedb8_8320;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+    let (a, b) = bytes.iter().fold((1, 0), |(a, b), &byte| {
+        let a = (a + byte as u32) % MODULUS;
+        (a, (b + a) % MODULUS)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The data of a zlib stream of stored deflate blocks, after checking its header, that only
+    /// its last block is marked as final, and its checksum
+    fn unzlib_stored(stream: &[u8]) -> Vec<u8> {
+        assert_eq!(u16::from_be_bytes([stream[0], stream[1]]) % 31, 0);
+        let mut rest = &stream[2..];
+        let mut data = Vec::new();
+        loop {
+            let is_final = rest[0] == 1;
+            let len = u16::from_le_bytes([rest[1], rest[2]]);
+            assert_eq!(u16::from_le_bytes([rest[3], rest[4]]), !len);
+            data.extend_from_slice(&rest[5..5 + len as usize]);
+            rest = &rest[5 + len as usize..];
+            if is_final {
+                break;
+            }
+        }
+        assert_eq!(rest, adler32(&data).to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn checksums_match_known_values() {
+        assert_eq!(!crc32(!0, b"IEND"), 0xae42_6082);
+        assert_eq!(!crc32(!0, b""), 0);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(b""), 1);
+    }
+
+    #[test]
+    fn stores_data_in_as_many_blocks_as_it_takes() {
+        assert_eq!(
+            zlib_stored(&[]),
+            [0x78, 0x01, 1, 0, 0, 0xff, 0xff, 0, 0, 0, 1]
+        );
+        for len in [
+            1,
+            MAX_STORED_LEN,
+            MAX_STORED_LEN + 1,
+            2 * MAX_STORED_LEN + 10,
+        ] {
+            let data = (0..len).map(|idx| idx as u8).collect::<Vec<_>>();
+            let stream = zlib_stored(&data);
+            // one 5 byte header for each block
+            let blocks = len.div_ceil(MAX_STORED_LEN);
+            assert_eq!(stream.len(), 2 + 5 * blocks + len + 4);
+            assert_eq!(unzlib_stored(&stream), data);
+        }
+    }
+
+    #[test]
+    fn writes_an_image_with_an_odd_width_byte_for_byte() {
+        let theme = Theme {
+            cell_size: 1,
+            ..Theme::default()
+        };
+        let mut generation = Generation::filled(0, 3, 2, Rule::CONWAY, Cell::Dead).unwrap();
+        generation.set(Position(0, 0), Cell::Alive);
+        generation.set(Position(2, 1), Cell::Alive);
+
+        let mut out = Vec::new();
+        write(&generation, &theme, &mut out).unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            // signature
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a,
+            // IHDR: 3x2, 1 bit per pixel, with a palette
+            0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+            0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x01, 0x03, 0x00, 0x00, 0x00,
+            0xa7, 0xba, 0xf4, 0x59,
+            // PLTE: black, white
+            0x00, 0x00, 0x00, 0x06, 0x50, 0x4c, 0x54, 0x45,
+            0x00, 0x00, 0x00, 0xff, 0xff, 0xff,
+            0xa5, 0xd9, 0x9f, 0xdd,
+            // IDAT: one stored block of two unfiltered rows, each padded to a whole byte
+            0x00, 0x00, 0x00, 0x0f, 0x49, 0x44, 0x41, 0x54,
+            0x78, 0x01, 0x01, 0x04, 0x00, 0xfb, 0xff, 0x00, 0x80, 0x00, 0x20, 0x01, 0xa4, 0x00, 0xa1,
+            0x9b, 0x8c, 0x9f, 0x7b,
+            // IEND
+            0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44,
+            0xae, 0x42, 0x60, 0x82,
+        ];
+        assert_eq!(out, expected);
+    }
+}
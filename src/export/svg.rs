@@ -0,0 +1,154 @@
+//! [SVG](https://www.w3.org/TR/SVG11/) images, which stay sharp at any size when printed
+
+use std::io::{self, Write};
+
+use super::*;
+
+/// Height of the line below the grid which the caption is written on, in pixels
+const CAPTION_HEIGHT: usize = 20;
+const CAPTION_FONT_SIZE: usize = 14;
+
+/// The same size and layout as the other formats, with a single rect for each run of live cells in
+/// a row
+pub fn write<W: Write>(generation: &Generation, theme: &Theme, out: &mut W) -> io::Result<()> {
+    let (width, grid_height) = theme.image_size(generation);
+    let height = grid_height + if theme.caption { CAPTION_HEIGHT } else { 0 };
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" shape-rendering="crispEdges">"#,
+        w = width,
+        h = height
+    )?;
+    writeln!(
+        out,
+        r#"<rect width="{}" height="{}" fill="{}"/>"#,
+        width, height, theme.dead
+    )?;
+
+    writeln!(out, r#"<g fill="{}">"#, theme.alive)?;
+    let size = theme.cell_size;
+    for (Position(x, y), len) in live_runs(generation) {
+        writeln!(
+            out,
+            r#"<rect x="{}" y="{}" width="{}" height="{}"/>"#,
+            x as usize * size,
+            y as usize * size,
+            len * size,
+            size
+        )?;
+    }
+    writeln!(out, "</g>")?;
+
+    // on top of the live cells, in the middle of the first row and column of pixels of each cell
+    if let Some(colour) = theme.grid {
+        write!(out, r#"<path stroke="{}" stroke-width="1" d=""#, colour)?;
+        for y in 0..=generation.height() {
+            write!(out, "M0 {}.5H{}", y * size, width)?;
+        }
+        for x in 0..=generation.width() {
+            write!(out, "M{}.5 0V{}", x * size, grid_height)?;
+        }
+        writeln!(out, r#""/>"#)?;
+    }
+
+    if theme.caption {
+        writeln!(
+            out,
+            r#"<text x="4" y="{}" font-family="sans-serif" font-size="{}" fill="{}">Generation {}</text>"#,
+            grid_height + CAPTION_FONT_SIZE + 2,
+            CAPTION_FONT_SIZE,
+            theme.alive,
+            generation.index()
+        )?;
+    }
+    writeln!(out, "</svg>")
+}
+
+/// The leftmost cell and length of each horizontal run of live cells on the grid, row by row
+fn live_runs(generation: &Generation) -> Vec<(Position, usize)> {
+    let grid = (
+        Position(0, 0),
+        Position::from((generation.width(), generation.height())),
+    );
+    let mut cells = generation.live_cells(Some(grid));
+    cells.sort_by_key(|position| (position.y(), position.x()));
+
+    let mut runs: Vec<(Position, usize)> = Vec::new();
+    for position in cells {
+        match runs.last_mut() {
+            Some((start, len))
+                if start.y() == position.y() && start.x() + *len as isize == position.x() =>
+            {
+                *len += 1;
+            }
+            _ => runs.push((position, 1)),
+        }
+    }
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A row of 3 live cells, a gap, then a lone live cell, with another on the row below
+    fn generation() -> Generation {
+        let mut generation = Generation::filled(7, 6, 2, Rule::CONWAY, Cell::Dead).unwrap();
+        for &(x, y) in &[(0, 0), (1, 0), (2, 0), (4, 0), (4, 1)] {
+            generation.set(Position(x, y), Cell::Alive);
+        }
+        generation
+    }
+
+    fn svg(theme: &Theme) -> String {
+        let mut out = Vec::new();
+        write(&generation(), theme, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn merges_neighbouring_live_cells_in_a_row_into_one_run() {
+        assert_eq!(
+            live_runs(&generation()),
+            [
+                (Position(0, 0), 3),
+                (Position(4, 0), 1),
+                (Position(4, 1), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_one_rect_for_each_run_of_live_cells() {
+        let svg = svg(&Theme::default());
+        let rects = svg
+            .lines()
+            .filter(|line| line.starts_with("<rect x="))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rects,
+            [
+                r#"<rect x="0" y="0" width="12" height="4"/>"#,
+                r#"<rect x="16" y="0" width="4" height="4"/>"#,
+                r#"<rect x="16" y="4" width="4" height="4"/>"#,
+            ]
+        );
+        assert!(!svg.contains("<path"));
+        assert!(!svg.contains("<text"));
+    }
+
+    #[test]
+    fn writes_grid_lines_and_a_caption_when_asked_to() {
+        let svg = svg(&Theme {
+            grid: Some(Colour(0x80, 0x80, 0x80)),
+            caption: true,
+            ..Theme::default()
+        });
+        // one line above each row and left of each column, and another at the bottom and right
+        assert!(svg.contains(
+            r##"<path stroke="#808080" stroke-width="1" d="M0 0.5H25M0 4.5H25M0 8.5H25M0.5 0V9M4.5 0V9M8.5 0V9M12.5 0V9M16.5 0V9M20.5 0V9M24.5 0V9"/>"##
+        ));
+        assert!(svg.contains(">Generation 7</text>"));
+        assert!(svg.contains(r#"height="29""#));
+    }
+}
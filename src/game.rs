@@ -1,6 +1,14 @@
-use std::ops;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "parallel")]
+use std::thread;
+use std::{error, fmt, mem, ops};
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub use crate::hashlife::Universe;
+use crate::hashlife::{self, HashLife};
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Position(pub isize, pub isize);
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -9,12 +17,76 @@ pub enum Cell {
     Dead,
 }
 
+/// Outer-totalistic rule of a Life-like cellular automaton, e.g. `B3/S23` for Conway's Game of Life
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Rule {
+    /// Bit `n` is set if a dead cell with `n` live neighbours should be born
+    birth: u16,
+    /// Bit `n` is set if a live cell with `n` live neighbours should survive
+    survival: u16,
+}
+
+/// How the edges of the grid are joined together, if at all
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Topology {
+    /// Opposite edges are joined, so anything which goes off one edge comes back on the other
+    Torus,
+    /// Nothing is joined, and every cell off the grid is dead
+    Plane,
+    /// Like a torus, but anything which goes off the top or bottom edge comes back mirrored
+    /// horizontally
+    Klein,
+    /// Like a torus, but anything which goes off any edge comes back mirrored along that edge
+    CrossSurface,
+    /// Nothing is joined, but nothing is ever off the grid either, as the grid is just a window
+    /// onto an infinite plane
+    Unbounded,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParsePositionError;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct ParseTopologyError;
+
+/// The cells of a grid wouldn't fit in memory
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct GridTooBigError(pub usize, pub usize);
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum ParseRuleError {
+    /// Not in `B.../S...` notation and doesn't have exactly one `/` for `S.../B...` notation
+    Malformed,
+    /// Neighbour counts must be digits in the range `[0, 8]`
+    BadNeighbourCount(char),
+}
+
 #[derive(Clone)]
 pub struct Generation {
     width: usize,
     height: usize,
     index: usize,
-    cells: Vec<Cell>,
+    rule: Rule,
+    topology: Topology,
+    /// Bit-packed rows of `words_per_row` words each, where bit `x % 64` of word `x / 64` of a row
+    /// is set if the cell in column `x` is alive. Bits past the end of a row are always clear.
+    cells: Vec<u64>,
+    /// Holds every cell instead of `cells` if the topology is unbounded, and is `None` otherwise
+    universe: Option<Universe>,
+    /// Of the last HashLife jump around the torus, shared with every generation since so that the
+    /// next jump can reuse whatever it's already worked out
+    engine: Option<Arc<Mutex<HashLife>>>,
+    /// Number of live `cells`, unused if there's a `universe`
+    population: usize,
+    /// Of the live `cells`, as returned by [`Generation::bounds`], unused if there's a `universe`
+    bounds: Option<(Position, Position)>,
+    /// Number of cells which came alive since the generation this was advanced from
+    births: usize,
+    /// Number of cells which died since the generation this was advanced from
+    deaths: usize,
+    /// Number of threads to step each generation with, each taking a band of rows
+    #[cfg(feature = "parallel")]
+    threads: usize,
 }
 
 impl Position {
@@ -25,6 +97,14 @@ impl Position {
     pub fn y(&self) -> isize {
         self.1
     }
+
+    /// `None` if either coordinate would overflow
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        Some(Self(
+            self.x().checked_add(rhs.x())?,
+            self.y().checked_add(rhs.y())?,
+        ))
+    }
 }
 
 impl ops::Add<Self> for Position {
@@ -54,6 +134,27 @@ impl From<(usize, usize)> for Position {
     }
 }
 
+impl FromStr for Position {
+    type Err = ParsePositionError;
+
+    /// e.g. `12,-5`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut coords = s.splitn(2, ',').map(|coord| coord.trim().parse());
+        match (coords.next(), coords.next()) {
+            (Some(Ok(x)), Some(Ok(y))) => Ok(Self(x, y)),
+            (_, _) => Err(ParsePositionError),
+        }
+    }
+}
+
+impl error::Error for ParsePositionError {}
+
+impl fmt::Display for ParsePositionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected a position like \"12,-5\"")
+    }
+}
+
 impl Cell {
     pub fn is_alive(&self) -> bool {
         match self {
@@ -74,39 +175,301 @@ impl ops::Not for Cell {
     }
 }
 
+impl Rule {
+    /// `B3/S23`
+    pub const CONWAY: Self = Self {
+        birth: 1 << 3,
+        survival: 1 << 2 | 1 << 3,
+    };
+
+    const MAX_NEIGHBOUR_COUNT: u32 = 8;
+
+    pub fn next_state(&self, cell: Cell, live_neighbour_count: usize) -> Cell {
+        let counts = match cell {
+            Cell::Alive => self.survival,
+            Cell::Dead => self.birth,
+        };
+        if counts & (1 << live_neighbour_count) != 0 {
+            Cell::Alive
+        } else {
+            Cell::Dead
+        }
+    }
+
+    /// Whether dead cells with no live neighbours are born, in which case nothing is ever stable
+    pub fn births_from_nothing(&self) -> bool {
+        self.birth & 1 != 0
+    }
+
+    fn parse_counts(counts: &str) -> Result<u16, ParseRuleError> {
+        counts.chars().try_fold(0, |acc, ch| match ch.to_digit(10) {
+            Some(n) if n <= Self::MAX_NEIGHBOUR_COUNT => Ok(acc | 1 << n),
+            _ => Err(ParseRuleError::BadNeighbourCount(ch)),
+        })
+    }
+
+    fn fmt_counts(counts: u16, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (0..=Self::MAX_NEIGHBOUR_COUNT)
+            .filter(|n| counts & (1 << n) != 0)
+            .try_for_each(|n| write!(f, "{}", n))
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self::CONWAY
+    }
+}
+
+impl FromStr for Rule {
+    type Err = ParseRuleError;
+
+    /// Accepts both `B.../S...` notation (e.g. `B36/S23`, `b2/s`) and the older `S.../B...`
+    /// notation (e.g. `23/36`)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.trim().split('/').collect::<Vec<_>>();
+        let (mut birth, mut survival) = (None, None);
+        for part in &parts {
+            match part.chars().next() {
+                Some('B') | Some('b') if birth.is_none() => {
+                    birth = Some(Self::parse_counts(&part[1..])?)
+                }
+                Some('S') | Some('s') if survival.is_none() => {
+                    survival = Some(Self::parse_counts(&part[1..])?)
+                }
+                _ => {
+                    birth = None;
+                    survival = None;
+                    break;
+                }
+            }
+        }
+        match (birth, survival, parts.as_slice()) {
+            (Some(birth), Some(survival), _) => Ok(Self { birth, survival }),
+            (_, _, [survival, birth]) => Ok(Self {
+                birth: Self::parse_counts(birth)?,
+                survival: Self::parse_counts(survival)?,
+            }),
+            (_, _, _) => Err(ParseRuleError::Malformed),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    /// `B.../S...` notation, or the older `S.../B...` notation with the alternate flag (`{:#}`)
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            Self::fmt_counts(self.survival, f)?;
+            f.write_str("/")?;
+            Self::fmt_counts(self.birth, f)
+        } else {
+            f.write_str("B")?;
+            Self::fmt_counts(self.birth, f)?;
+            f.write_str("/S")?;
+            Self::fmt_counts(self.survival, f)
+        }
+    }
+}
+
+impl error::Error for ParseRuleError {}
+
+impl fmt::Display for ParseRuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Malformed => f.write_str("expected a rulestring like \"B3/S23\" or \"23/3\""),
+            Self::BadNeighbourCount(ch) => {
+                write!(f, "bad neighbour count '{}', expected a digit 0-8", ch)
+            }
+        }
+    }
+}
+
+impl FromStr for Topology {
+    type Err = ParseTopologyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "torus" => Ok(Self::Torus),
+            "plane" => Ok(Self::Plane),
+            "klein" => Ok(Self::Klein),
+            "cross-surface" => Ok(Self::CrossSurface),
+            "unbounded" => Ok(Self::Unbounded),
+            _ => Err(ParseTopologyError),
+        }
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Torus => "torus",
+            Self::Plane => "plane",
+            Self::Klein => "klein",
+            Self::CrossSurface => "cross-surface",
+            Self::Unbounded => "unbounded",
+        })
+    }
+}
+
+impl error::Error for ParseTopologyError {}
+
+impl fmt::Display for ParseTopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("expected one of: torus, plane, klein, cross-surface, unbounded")
+    }
+}
+
+impl error::Error for GridTooBigError {}
+
+impl fmt::Display for GridTooBigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "a {}x{} grid is too big to fit in memory",
+            self.0, self.1
+        )
+    }
+}
+
 impl Generation {
-    pub fn filled(index: usize, width: usize, height: usize, filler: Cell) -> Self {
-        let cells = vec![filler; width * height];
-        Self {
+    pub fn filled(
+        index: usize,
+        width: usize,
+        height: usize,
+        rule: Rule,
+        filler: Cell,
+    ) -> Result<Self, GridTooBigError> {
+        let len = Self::words_per_row_of(width)
+            .checked_mul(height)
+            .filter(|&len| len <= isize::MAX as usize / mem::size_of::<u64>())
+            .ok_or(GridTooBigError(width, height))?;
+        let mut generation = Self {
             width,
             height,
             index,
-            cells,
+            rule,
+            topology: Topology::Torus,
+            cells: vec![0; len],
+            universe: None,
+            engine: None,
+            population: 0,
+            bounds: None,
+            births: 0,
+            deaths: 0,
+            #[cfg(feature = "parallel")]
+            threads: 1,
+        };
+        if filler.is_alive() {
+            generation.population = width * height;
+            for y in 0..height {
+                let row = generation.row_mut(y);
+                row.fill(!0);
+                Self::clear_past_end(row, width);
+            }
+            generation.bounds = generation.scan_bounds();
         }
+        Ok(generation)
     }
 
-    pub fn generate<F>(index: usize, width: usize, height: usize, cell_generator: &mut F) -> Self
-    where
-        F: FnMut() -> Cell,
-    {
-        let mut cells = Vec::with_capacity(width * height);
-        for _ in 0..width * height {
-            cells.push(cell_generator());
-        }
+    /// Unbounded, with every cell of the `universe` along with its index and rule. The grid is only
+    /// the part of the plane which is shown at first.
+    pub fn from_universe(width: usize, height: usize, universe: Universe) -> Self {
         Self {
             width,
             height,
-            index,
-            cells,
+            index: universe.index(),
+            rule: universe.rule(),
+            topology: Topology::Unbounded,
+            cells: Vec::new(),
+            universe: Some(universe),
+            engine: None,
+            population: 0,
+            bounds: None,
+            births: 0,
+            deaths: 0,
+            #[cfg(feature = "parallel")]
+            threads: 1,
+        }
+    }
+
+    pub fn generate<F>(
+        index: usize,
+        width: usize,
+        height: usize,
+        rule: Rule,
+        cell_generator: &mut F,
+    ) -> Result<Self, GridTooBigError>
+    where
+        F: FnMut() -> Cell,
+    {
+        let mut generation = Self::filled(index, width, height, rule, Cell::Dead)?;
+        for y in 0..height {
+            for x in 0..width {
+                generation.set(Position::from((x, y)), cell_generator());
+            }
         }
+        Ok(generation)
     }
 
     pub fn nth_after(seed: &Self, n: usize) -> Self {
+        if let Some(universe) = &seed.universe {
+            let next_universe = Universe::nth_after(universe, n);
+            let survivors = next_universe.common_population(universe) as usize;
+            let mut it = seed.clone();
+            it.index += n;
+            it.births = next_universe.population() as usize - survivors;
+            it.deaths = universe.population() as usize - survivors;
+            it.universe = Some(next_universe);
+            return it;
+        }
         // TODO: avoid this copy?
         let mut it = seed.clone();
-        for _ in 0..n {
+        let mut remaining = n;
+        while remaining >= hashlife::MIN_JUMP && it.topology() == Topology::Torus {
+            let engine = match &it.engine {
+                Some(engine) if !engine.lock().expect(hashlife::ENGINE_POISONED).is_full() => {
+                    engine.clone()
+                }
+                _ => Arc::new(Mutex::new(HashLife::new(it.rule()))),
+            };
+            // HashLife only gets ahead if it can reuse enough of its results, which chaotic
+            // patterns don't let it, so give up before it takes longer than stepping through the
+            // next few generations would. Chaotic patterns often settle down, so step through
+            // them and try again.
+            let chunk = remaining / hashlife::CHUNKS_PER_ATTEMPT;
+            let budget = chunk.saturating_mul(it.cells.len()) / hashlife::WORD_STEPS_PER_RESULT;
+            let jumped = engine
+                .lock()
+                .expect(hashlife::ENGINE_POISONED)
+                .nth_after(&it, remaining, budget);
+            match jumped {
+                Some(jumped) => {
+                    it = jumped;
+                    remaining = 0;
+                }
+                None => {
+                    for _ in 0..chunk {
+                        it = it.next();
+                    }
+                    remaining -= chunk;
+                }
+            }
+            // even if it gave up, whatever it worked out along the way can still be reused
+            it.engine = Some(engine);
+        }
+        for _ in 0..remaining {
             it = it.next();
         }
+        // `next` doesn't keep count, as it's cheaper to only count once at the end
+        it.population = 0;
+        it.births = 0;
+        it.deaths = 0;
+        for (&word, &seed_word) in it.cells.iter().zip(&seed.cells) {
+            it.population += word.count_ones() as usize;
+            it.births += (word & !seed_word).count_ones() as usize;
+            it.deaths += (seed_word & !word).count_ones() as usize;
+        }
+        it.bounds = it.scan_bounds();
         it
     }
 
@@ -122,72 +485,707 @@ impl Generation {
         self.index
     }
 
-    fn next(&self) -> Self {
-        let mut next = Self::filled(self.index() + 1, self.width(), self.height(), Cell::Dead);
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Every cell of an unbounded generation, or `None` if it has a grid
+    pub fn universe(&self) -> Option<&Universe> {
+        self.universe.as_ref()
+    }
+
+    /// Number of live cells
+    pub fn population(&self) -> usize {
+        match &self.universe {
+            Some(universe) => universe.population() as usize,
+            None => self.population,
+        }
+    }
+
+    /// Number of cells which came alive since the generation this was advanced from by
+    /// [`Generation::nth_after`], or `0` if it wasn't
+    pub fn births(&self) -> usize {
+        self.births
+    }
+
+    /// Number of cells which died since the generation this was advanced from by
+    /// [`Generation::nth_after`], or `0` if it wasn't
+    pub fn deaths(&self) -> usize {
+        self.deaths
+    }
+
+    /// Top-left corner (inclusive) and bottom-right corner (exclusive) of the smallest rectangle
+    /// which contains every live cell, or `None` if there aren't any
+    pub fn bounds(&self) -> Option<(Position, Position)> {
+        match &self.universe {
+            Some(universe) => universe.bounds(),
+            None => self.bounds,
+        }
+    }
+
+    /// Work out the [`Generation::bounds`] of the live `cells` from scratch
+    fn scan_bounds(&self) -> Option<(Position, Position)> {
+        let (mut min_x, mut max_x) = (usize::MAX, 0);
+        let (mut min_y, mut max_y) = (None, 0);
         for y in 0..self.height() {
-            for x in 0..self.width() {
-                let position = Position::from((x, y));
-                let live_neighbour_count = self
-                    .neighbouring_cells(position)
-                    .iter()
-                    .filter(|cell| cell.is_alive())
-                    .count();
-                next[position] = match (live_neighbour_count, self[position]) {
-                    (3, _) => Cell::Alive,
-                    (2, Cell::Alive) => Cell::Alive,
-                    _ => Cell::Dead,
-                };
+            let row = self.row(y);
+            let first_word = row.iter().position(|&word| word != 0);
+            let last_word = row.iter().rposition(|&word| word != 0);
+            if let (Some(first_word), Some(last_word)) = (first_word, last_word) {
+                min_x = min_x.min(first_word * 64 + row[first_word].trailing_zeros() as usize);
+                max_x = max_x.max(last_word * 64 + 64 - row[last_word].leading_zeros() as usize);
+                min_y = min_y.or(Some(y));
+                max_y = y + 1;
             }
         }
-        next
+        min_y.map(|min_y| {
+            (
+                Position::from((min_x, min_y)),
+                Position::from((max_x, max_y)),
+            )
+        })
     }
 
-    pub fn neighbouring_cells(&self, relative_to: Position) -> Vec<Cell> {
-        static OFFSETS: [Position; 8] = [
-            Position(-1, -1),
-            Position(0, -1),
-            Position(1, -1),
-            Position(-1, 0),
-            Position(1, 0),
-            Position(-1, 1),
-            Position(0, 1),
-            Position(1, 1),
-        ];
-        OFFSETS
-            .iter()
-            .map(|offset| self[relative_to + *offset])
-            .collect()
+    /// Any cells outside of the grid are wrapped according to the new topology, or left out if
+    /// it's a plane
+    pub fn with_topology(mut self, topology: Topology) -> Self {
+        let live_cells = self.live_cells(None);
+        self.topology = topology;
+        match (topology, self.universe.is_some()) {
+            (Topology::Unbounded, false) => {
+                self.cells = Vec::new();
+                self.universe = Some(Universe::new(self.index, self.rule, &live_cells));
+            }
+            (Topology::Unbounded, true) => {}
+            (_, true) => {
+                self.cells = vec![0; self.words_per_row() * self.height()];
+                self.universe = None;
+                self.population = 0;
+                self.bounds = None;
+                for position in live_cells {
+                    self.set(position, Cell::Alive);
+                }
+            }
+            (_, false) => {}
+        }
+        self
     }
 
-    fn cell_idx(&self, position: Position) -> usize {
-        let x = if position.x() < 0 {
-            (position.x() + self.width() as isize) as usize
-        } else {
-            position.x() as usize % self.width()
+    /// A generation with the same size and settings as this one, but where every cell is dead
+    pub fn blank(&self, index: usize) -> Self {
+        let (width, height) = (self.width(), self.height());
+        let mut blank = match &self.universe {
+            Some(_) => Self::from_universe(width, height, Universe::new(index, self.rule(), &[])),
+            None => Self::filled(index, width, height, self.rule(), Cell::Dead)
+                .expect("Expected a grid the same size as an existing one to fit in memory")
+                .with_topology(self.topology()),
         };
-        let y = if position.y() < 0 {
-            (position.y() + self.height() as isize) as usize
-        } else {
-            position.y() as usize % self.height()
+        blank.engine = self.engine.clone();
+        #[cfg(feature = "parallel")]
+        let blank = blank.with_threads(self.threads());
+        blank
+    }
+
+    #[cfg(feature = "parallel")]
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Step each subsequent generation with this many threads, where `1` doesn't spawn any
+    #[cfg(feature = "parallel")]
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Positions of the live cells from the top-left corner of the `bounds` (inclusive) to the
+    /// bottom-right corner (exclusive), or of every live cell if there are no `bounds`. Nothing is
+    /// wrapped, so only unbounded generations can have live cells outside of the grid.
+    pub fn live_cells(&self, bounds: Option<(Position, Position)>) -> Vec<Position> {
+        if let Some(universe) = &self.universe {
+            return universe.live_cells(bounds);
+        }
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let (min, max) = bounds.unwrap_or((Position(0, 0), Position(width, height)));
+        let (min_x, max_x) = (min.x().clamp(0, width), max.x().clamp(0, width));
+        let (min_y, max_y) = (min.y().clamp(0, height), max.y().clamp(0, height));
+
+        let mut cells = Vec::new();
+        if min_x >= max_x {
+            return cells;
+        }
+        for y in min_y..max_y {
+            let row = self.row(y as usize);
+            let (first_word, last_word) = (min_x as usize / 64, (max_x as usize - 1) / 64);
+            for (word_idx, &word) in row.iter().enumerate().take(last_word + 1).skip(first_word) {
+                let word_x = word_idx as isize * 64;
+                // only the bits from `min_x` to `max_x`
+                let (from, to) = ((min_x - word_x).max(0), (max_x - word_x).min(64));
+                let mut word = word & (!0 >> (64 - to)) & (!0 << from);
+                while word != 0 {
+                    cells.push(Position(word_x + word.trailing_zeros() as isize, y));
+                    word &= word - 1;
+                }
+            }
+        }
+        cells
+    }
+
+    /// Positions outside of `[0, self.width)`, `[0, self.height)` are wrapped according to the
+    /// topology, and ignored if they're off the edge of a plane
+    pub fn set(&mut self, position: Position, cell: Cell) {
+        if let Some(universe) = &mut self.universe {
+            universe.set(position, cell);
+            return;
+        }
+        let (word_idx, bit) = match self.word_idx(position) {
+            Some(word_idx) => word_idx,
+            None => return,
         };
-        x + y * self.width()
+        let was_alive = self.cells[word_idx] >> bit & 1 != 0;
+        match cell {
+            Cell::Alive => self.cells[word_idx] |= 1 << bit,
+            Cell::Dead => self.cells[word_idx] &= !(1 << bit),
+        }
+        self.population = self.population + cell.is_alive() as usize - was_alive as usize;
+
+        let position = Position::from((
+            word_idx % self.words_per_row() * 64 + bit,
+            word_idx / self.words_per_row(),
+        ));
+        match (cell, self.bounds) {
+            (Cell::Alive, None) => self.bounds = Some((position, position + Position(1, 1))),
+            (Cell::Alive, Some((min, max))) => {
+                let min = Position(min.x().min(position.x()), min.y().min(position.y()));
+                let max = Position(max.x().max(position.x() + 1), max.y().max(position.y() + 1));
+                self.bounds = Some((min, max));
+            }
+            // the bounds can only shrink if the cell was on one of their edges
+            (Cell::Dead, Some((min, max)))
+                if was_alive
+                    && (position.x() == min.x()
+                        || position.y() == min.y()
+                        || position.x() == max.x() - 1
+                        || position.y() == max.y() - 1) =>
+            {
+                self.bounds = self.scan_bounds();
+            }
+            (Cell::Dead, _) => {}
+        }
+    }
+
+    fn next(&self) -> Self {
+        let mut next = self.blank(self.index() + 1);
+        #[cfg(feature = "parallel")]
+        if self.threads > 1 {
+            // every band reads from the whole of `self`, so wrapping around the band edges is
+            // the same as for the serial path
+            let band_height = self.height().div_ceil(self.threads).max(1);
+            let band_len = band_height * self.words_per_row();
+            if band_len != 0 {
+                thread::scope(|scope| {
+                    for (band_idx, band) in next.cells.chunks_mut(band_len).enumerate() {
+                        scope.spawn(move || self.step_rows(band_idx * band_height, band));
+                    }
+                });
+            }
+            return next;
+        }
+        self.step_rows(0, &mut next.cells);
+        next
+    }
+
+    /// Steps all 64 cells of a word at once, by counting the live neighbours of each of them in
+    /// parallel with bitwise adders. `next_rows` are consecutive rows of the next generation,
+    /// starting with row `first_y`.
+    fn step_rows(&self, first_y: usize, next_rows: &mut [u64]) {
+        let words_per_row = self.words_per_row();
+        if words_per_row == 0 {
+            return;
+        }
+        let (mut west, mut east) = (vec![0; words_per_row], vec![0; words_per_row]);
+        let (mut above_buf, mut below_buf) = (Vec::new(), Vec::new());
+        let mut counts = vec![[0; 4]; words_per_row];
+        for (y, next_row) in (first_y..).zip(next_rows.chunks_mut(words_per_row)) {
+            let y = y as isize;
+            let above = self.neighbour_row(y - 1, &mut above_buf);
+            let centre = self.row(y as usize);
+            let below = self.neighbour_row(y + 1, &mut below_buf);
+
+            counts.iter_mut().for_each(|count| *count = [0; 4]);
+            for (row, row_y) in [(above, y - 1), (centre, y), (below, y + 1)] {
+                self.shift_row(row, row_y, &mut west, &mut east);
+                for (word_idx, count) in counts.iter_mut().enumerate() {
+                    add_bits(count, west[word_idx]);
+                    add_bits(count, east[word_idx]);
+                }
+            }
+            for (word_idx, count) in counts.iter_mut().enumerate() {
+                add_bits(count, above[word_idx]);
+                add_bits(count, below[word_idx]);
+            }
+
+            for ((next_word, count), &word) in next_row.iter_mut().zip(&counts).zip(centre) {
+                for n in 0..=Rule::MAX_NEIGHBOUR_COUNT as usize {
+                    let with_n = count_equals(count, n);
+                    if self.rule.birth & (1 << n) != 0 {
+                        *next_word |= with_n & !word;
+                    }
+                    if self.rule.survival & (1 << n) != 0 {
+                        *next_word |= with_n & word;
+                    }
+                }
+            }
+            Self::clear_past_end(next_row, self.width());
+        }
+    }
+
+    /// The cells of row `y`, which can be just off the top or bottom of the grid, in which case
+    /// the row is wrapped according to the topology. `buffer` holds the row if it had to be made.
+    fn neighbour_row<'a>(&'a self, y: isize, buffer: &'a mut Vec<u64>) -> &'a [u64] {
+        let height = self.height() as isize;
+        if (0..height).contains(&y) {
+            return self.row(y as usize);
+        }
+        let wrapped = self.row(y.rem_euclid(height) as usize);
+        buffer.clear();
+        match self.topology {
+            Topology::Torus => return wrapped,
+            Topology::Plane => buffer.resize(wrapped.len(), 0),
+            Topology::Unbounded => {
+                unreachable!("Unbounded generations are stepped by their universe")
+            }
+            Topology::Klein | Topology::CrossSurface => {
+                buffer.resize(wrapped.len(), 0);
+                let width = self.width();
+                for x in (0..width).filter(|&x| wrapped[x / 64] >> (x % 64) & 1 != 0) {
+                    let mirrored_x = width - 1 - x;
+                    buffer[mirrored_x / 64] |= 1 << (mirrored_x % 64);
+                }
+            }
+        }
+        buffer
+    }
+
+    /// Fill `west` with each cell's western neighbour, and `east` with each cell's eastern
+    /// neighbour, where `row` holds the cells of row `y` and the neighbours of the cells at either
+    /// end depend on the topology
+    fn shift_row(&self, row: &[u64], y: isize, west: &mut [u64], east: &mut [u64]) {
+        let width = self.width();
+        let west_of_row = self[Position(-1, y)].is_alive() as u64;
+        let east_of_row = self[Position(width as isize, y)].is_alive() as u64;
+        for (word_idx, &word) in row.iter().enumerate() {
+            let carry_west = match word_idx {
+                0 => west_of_row,
+                _ => row[word_idx - 1] >> 63,
+            };
+            west[word_idx] = word << 1 | carry_west;
+            east[word_idx] = match word_idx {
+                idx if idx + 1 == row.len() => word >> 1 | east_of_row << ((width - 1) % 64),
+                _ => word >> 1 | row[word_idx + 1] << 63,
+            };
+        }
+        Self::clear_past_end(west, width);
+        Self::clear_past_end(east, width);
+    }
+
+    fn words_per_row(&self) -> usize {
+        Self::words_per_row_of(self.width())
+    }
+
+    fn words_per_row_of(width: usize) -> usize {
+        width.div_ceil(64)
+    }
+
+    fn row(&self, y: usize) -> &[u64] {
+        let words_per_row = self.words_per_row();
+        &self.cells[y * words_per_row..(y + 1) * words_per_row]
+    }
+
+    fn row_mut(&mut self, y: usize) -> &mut [u64] {
+        let words_per_row = self.words_per_row();
+        &mut self.cells[y * words_per_row..(y + 1) * words_per_row]
+    }
+
+    /// Clear any bits of the last word of a `row` which are past the end of the row
+    fn clear_past_end(row: &mut [u64], width: usize) {
+        if let (Some(last), 1..=63) = (row.last_mut(), width % 64) {
+            *last &= (1 << (width % 64)) - 1;
+        }
+    }
+
+    /// The index of the word holding a cell, and the cell's bit within that word, or `None` if
+    /// the cell is off the edge of a plane
+    fn word_idx(&self, position: Position) -> Option<(usize, usize)> {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let (mut x, mut y) = (position.x(), position.y());
+        // odd if the position is on a mirrored copy of the grid
+        let (x_wraps, y_wraps) = (x.div_euclid(width), y.div_euclid(height));
+        match self.topology {
+            Topology::Torus => {}
+            Topology::Plane if x_wraps != 0 || y_wraps != 0 => return None,
+            Topology::Plane => {}
+            Topology::Klein => {
+                if y_wraps % 2 != 0 {
+                    x = -1 - x;
+                }
+            }
+            Topology::CrossSurface => {
+                if y_wraps % 2 != 0 {
+                    x = -1 - x;
+                }
+                if x_wraps % 2 != 0 {
+                    y = -1 - y;
+                }
+            }
+            Topology::Unbounded => unreachable!("Unbounded generations keep their cells elsewhere"),
+        }
+        let x = x.rem_euclid(width) as usize;
+        let y = y.rem_euclid(height) as usize;
+        Some((y * self.words_per_row() + x / 64, x % 64))
+    }
+}
+
+/// Add a 1-bit number to each of the 64 4-bit numbers of `count`, whose bits are spread over its
+/// words, least significant first
+fn add_bits(count: &mut [u64; 4], bits: u64) {
+    let mut carry = bits;
+    for word in count.iter_mut() {
+        let next_carry = *word & carry;
+        *word ^= carry;
+        carry = next_carry;
+    }
+}
+
+/// Bit `i` is set if the `i`th of the 64 4-bit numbers of `count` is `n`
+fn count_equals(count: &[u64; 4], n: usize) -> u64 {
+    count
+        .iter()
+        .enumerate()
+        .fold(!0, |equal, (bit, &word)| match n >> bit & 1 {
+            1 => equal & word,
+            _ => equal & !word,
+        })
+}
+
+impl Hash for Generation {
+    /// Only the cells are hashed, so generations which look the same hash the same whatever their
+    /// index. Unbounded generations only do if their cells are arranged in the same way internally,
+    /// which they usually will be after the same pattern is advanced by the same amount.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match &self.universe {
+            Some(universe) => universe.hash(state),
+            None => {
+                self.width.hash(state);
+                self.cells.hash(state);
+            }
+        }
     }
 }
 
 impl ops::Index<Position> for Generation {
     type Output = Cell;
 
-    /// Index will wrap around if outside of `[0, self.width)`, `[0, self.height)`
+    /// Positions outside of `[0, self.width)`, `[0, self.height)` are wrapped according to the
+    /// topology, and are dead if they're off the edge of a plane. Nothing is wrapped if it's
+    /// unbounded.
     fn index(&self, index: Position) -> &Self::Output {
-        let idx = self.cell_idx(index);
-        &self.cells[idx]
+        if let Some(universe) = &self.universe {
+            return match universe.cell(index) {
+                Cell::Alive => &Cell::Alive,
+                Cell::Dead => &Cell::Dead,
+            };
+        }
+        match self.word_idx(index) {
+            Some((word_idx, bit)) if self.cells[word_idx] >> bit & 1 != 0 => &Cell::Alive,
+            _ => &Cell::Dead,
+        }
     }
 }
 
-impl ops::IndexMut<Position> for Generation {
-    /// Index will wrap around if outside of `[0, self.width)`, `[0, self.height)`
-    fn index_mut(&mut self, index: Position) -> &mut Self::Output {
-        let idx = self.cell_idx(index);
-        &mut self.cells[idx]
+/// The live cells of a `generation` sorted into rows, and moved so that the top-left corner of
+/// their bounding box is at `(0, 0)`, for tests to compare patterns wherever they've got to
+#[cfg(test)]
+pub(super) fn shape(generation: &Generation) -> Vec<Position> {
+    let (min, _) = generation
+        .bounds()
+        .unwrap_or((Position(0, 0), Position(0, 0)));
+    let mut cells = generation
+        .live_cells(None)
+        .into_iter()
+        .map(|position| position + Position(-min.x(), -min.y()))
+        .collect::<Vec<_>>();
+    cells.sort_by_key(|position| (position.y(), position.x()));
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each cell is alive with probability 1/2, decided by an xorshift PRNG seeded with `seed`
+    fn soup(width: usize, height: usize, rule: Rule, seed: u64) -> Generation {
+        let mut state = seed.max(1);
+        let mut cell_generator = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            if state & 1 != 0 {
+                Cell::Alive
+            } else {
+                Cell::Dead
+            }
+        };
+        Generation::generate(0, width, height, rule, &mut cell_generator).unwrap()
+    }
+
+    /// A glider heading down and to the right, with the top-left corner of its bounding box at
+    /// `(2, 2)`
+    fn glider(width: usize, height: usize, topology: Topology) -> Generation {
+        let mut generation = Generation::filled(0, width, height, Rule::CONWAY, Cell::Dead)
+            .unwrap()
+            .with_topology(topology);
+        for position in crate::pattern::glider() {
+            generation.set(position + Position(2, 2), Cell::Alive);
+        }
+        generation
+    }
+
+    /// The shape of a glider, mirrored horizontally (left to right) or vertically (top to bottom)
+    fn mirrored_glider(horizontally: bool) -> Vec<Position> {
+        let mut cells = crate::pattern::glider()
+            .into_iter()
+            .map(|position| match horizontally {
+                true => Position(2 - position.x(), position.y()),
+                false => Position(position.x(), 2 - position.y()),
+            })
+            .collect::<Vec<_>>();
+        cells.sort_by_key(|position| (position.y(), position.x()));
+        cells
+    }
+
+    #[test]
+    fn rulestrings_parse_in_either_notation() {
+        let highlife = Rule {
+            birth: 1 << 3 | 1 << 6,
+            survival: 1 << 2 | 1 << 3,
+        };
+        assert_eq!("B36/S23".parse(), Ok(highlife));
+        assert_eq!("b36/s23".parse(), Ok(highlife));
+        assert_eq!("23/36".parse(), Ok(highlife));
+        assert_eq!("23/3".parse(), Ok(Rule::CONWAY));
+        let seeds = Rule {
+            birth: 1 << 2,
+            survival: 0,
+        };
+        assert_eq!("B2/S".parse(), Ok(seeds));
+        assert_eq!("/2".parse(), Ok(seeds));
+
+        assert_eq!(highlife.to_string(), "B36/S23");
+        assert_eq!(format!("{:#}", highlife), "23/36");
+    }
+
+    #[test]
+    fn malformed_rulestrings_are_rejected() {
+        for &malformed in &["", "3", "B3/S23/S4", "B3/S2/3"] {
+            assert_eq!(
+                malformed.parse::<Rule>(),
+                Err(ParseRuleError::Malformed),
+                "{:?}",
+                malformed
+            );
+        }
+        assert_eq!(
+            "B9/S23".parse::<Rule>(),
+            Err(ParseRuleError::BadNeighbourCount('9'))
+        );
+        assert_eq!(
+            "B3S23".parse::<Rule>(),
+            Err(ParseRuleError::BadNeighbourCount('S'))
+        );
+        assert_eq!(
+            "23/3x".parse::<Rule>(),
+            Err(ParseRuleError::BadNeighbourCount('x'))
+        );
+    }
+
+    /// Advance one generation at a time, never taking the HashLife path
+    fn step_n(seed: &Generation, n: usize) -> Generation {
+        let mut stepped = (0..n).fold(seed.clone(), |generation, _| generation.next());
+        // `next` doesn't keep the bounds up to date either
+        stepped.bounds = stepped.scan_bounds();
+        stepped
+    }
+
+    #[test]
+    fn hashlife_jump_matches_stepping_on_a_non_square_torus() {
+        let n = hashlife::MIN_JUMP + 5;
+        let mut seed_gen = soup(70, 23, Rule::CONWAY, 1);
+        // a glider keeps things moving across the seams once the soup has settled down
+        for position in crate::pattern::glider() {
+            seed_gen.set(position, Cell::Alive);
+        }
+
+        // without a budget, so that it never gives up and steps instead
+        let jumped = HashLife::new(seed_gen.rule())
+            .nth_after(&seed_gen, n, usize::MAX)
+            .unwrap();
+        let stepped = step_n(&seed_gen, n);
+        assert_eq!(jumped.index(), stepped.index());
+        assert_eq!(jumped.live_cells(None), stepped.live_cells(None));
+    }
+
+    #[test]
+    fn jumps_which_give_up_or_reuse_an_engine_match_stepping() {
+        let n = hashlife::MIN_JUMP * 2;
+        // a soup of a chaotic rule, which HashLife should give up on at least once, and a lone
+        // glider, which is regular enough that it shouldn't
+        let mut glider = Generation::filled(0, 128, 128, Rule::CONWAY, Cell::Dead).unwrap();
+        for position in crate::pattern::glider() {
+            glider.set(position, Cell::Alive);
+        }
+        let seeds = [
+            (soup(96, 40, "B2/S".parse().unwrap(), 2), true),
+            (glider, false),
+        ];
+        for (seed_gen, expect_give_up) in &seeds {
+            let once = Generation::nth_after(seed_gen, n);
+            let twice = Generation::nth_after(&once, n);
+            let stepped = step_n(seed_gen, n * 2);
+            assert_eq!(twice.index(), stepped.index());
+            assert_eq!(twice.live_cells(None), stepped.live_cells(None));
+            assert_eq!(twice.population(), twice.live_cells(None).len());
+
+            let engine = twice.engine.as_ref().expect("Should have tried to jump");
+            let first_engine = once.engine.as_ref().expect("Should have tried to jump");
+            assert!(
+                Arc::ptr_eq(engine, first_engine),
+                "Should have reused the engine"
+            );
+            let give_ups = engine.lock().expect(hashlife::ENGINE_POISONED).give_ups();
+            assert_eq!(give_ups != 0, *expect_give_up, "gave up {} times", give_ups);
+        }
+    }
+
+    #[test]
+    fn huge_jumps_of_an_unbounded_glider_stay_on_the_plane() {
+        let glider = Universe::new(0, Rule::CONWAY, &crate::pattern::glider());
+        let seed_gen = Generation::from_universe(8, 8, glider);
+        // far enough that the jump has to be split up to fit in roots which can be positioned
+        let jumped = Generation::nth_after(&seed_gen, 1 << 61);
+        // a glider moves one cell diagonally every 4 generations
+        let distance = 1 << 59;
+        let moved = crate::pattern::glider()
+            .into_iter()
+            .map(|position| position + Position(distance, distance))
+            .collect::<Vec<_>>();
+        let mut cells = jumped.live_cells(None);
+        cells.sort_by_key(|position| (position.y(), position.x()));
+        assert_eq!(cells, moved);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn stepping_in_parallel_matches_stepping_serially() {
+        // unbounded generations are stepped by their universe instead, so never in parallel
+        let topologies = [
+            Topology::Torus,
+            Topology::Plane,
+            Topology::Klein,
+            Topology::CrossSurface,
+        ];
+        // whole words per row, and rows which end partway through a word
+        let widths = [64, 128, 70, 37];
+        for &topology in &topologies {
+            for (seed, &width) in widths.iter().enumerate() {
+                let seed_gen =
+                    soup(width, 29, Rule::CONWAY, seed as u64 + 1).with_topology(topology);
+                let serial = step_n(&seed_gen, 20);
+                for &threads in &[2, 3, 8] {
+                    let parallel = step_n(&seed_gen.clone().with_threads(threads), 20);
+                    assert_eq!(
+                        parallel.live_cells(None),
+                        serial.live_cells(None),
+                        "{:?} {} wide with {} threads",
+                        topology,
+                        width,
+                        threads
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gliders_crash_into_the_edges_of_a_plane() {
+        let crashed = step_n(&glider(12, 12, Topology::Plane), 60);
+        // either nothing is left, or a block
+        let block = [(0, 0), (1, 0), (0, 1), (1, 1)];
+        let block = block
+            .iter()
+            .map(|&(x, y)| Position(x, y))
+            .collect::<Vec<_>>();
+        let shape = shape(&crashed);
+        assert!(shape.is_empty() || shape == block, "{:?}", shape);
+        assert_eq!(
+            step_n(&crashed, 1).live_cells(None),
+            crashed.live_cells(None)
+        );
+    }
+
+    #[test]
+    fn gliders_come_back_the_same_across_the_seams_of_a_torus() {
+        // far enough to have crossed both seams, and to be clear of them again
+        let crossed = step_n(&glider(16, 12, Topology::Torus), 4 * 16);
+        assert_eq!(shape(&crossed), crate::pattern::glider());
+    }
+
+    #[test]
+    fn gliders_come_back_mirrored_across_the_top_and_bottom_seam_of_a_klein_bottle() {
+        // far enough to have crossed the bottom seam, but not the left or right one
+        let crossed = step_n(&glider(32, 12, Topology::Klein), 4 * 14);
+        assert_eq!(shape(&crossed), mirrored_glider(true));
+    }
+
+    #[test]
+    fn gliders_come_back_mirrored_across_each_seam_of_a_cross_surface() {
+        let seed_gen = glider(12, 20, Topology::CrossSurface);
+        // far enough to have crossed the right seam, after which it heads up rather than down
+        let crossed = step_n(&seed_gen, 4 * 12);
+        assert_eq!(shape(&crossed), mirrored_glider(false));
+        // then across the top seam too, after which it's been mirrored both ways
+        let crossed = step_n(&crossed, 4 * 6);
+        let mut both_ways = crate::pattern::glider()
+            .into_iter()
+            .map(|position| Position(2 - position.x(), 2 - position.y()))
+            .collect::<Vec<_>>();
+        both_ways.sort_by_key(|position| (position.y(), position.x()));
+        assert_eq!(shape(&crossed), both_ways);
+    }
+
+    #[test]
+    fn bounds_are_kept_up_to_date_as_cells_are_set() {
+        let mut generation = Generation::filled(0, 70, 10, Rule::CONWAY, Cell::Dead).unwrap();
+        assert_eq!(generation.bounds(), None);
+        // wrapped around the torus, so that the bounds are of the cell which actually came alive
+        generation.set(Position(-1, 3), Cell::Alive);
+        generation.set(Position(4, 12), Cell::Alive);
+        generation.set(Position(30, 5), Cell::Alive);
+        assert_eq!(generation.bounds(), Some((Position(4, 2), Position(70, 6))));
+        assert_eq!(generation.bounds(), generation.scan_bounds());
+
+        for (position, bounds) in [
+            (Position(30, 5), Some((Position(4, 2), Position(70, 4)))),
+            (Position(69, 3), Some((Position(4, 2), Position(5, 3)))),
+            (Position(4, 2), None),
+        ] {
+            generation.set(position, Cell::Dead);
+            assert_eq!(generation.bounds(), bounds);
+            assert_eq!(generation.bounds(), generation.scan_bounds());
+        }
     }
 }
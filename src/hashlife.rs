@@ -0,0 +1,873 @@
+//! [HashLife](https://conwaylife.com/wiki/HashLife): canonicalised quadtrees with memoised results,
+//! which can jump ahead by `2^k` generations in roughly `O(k)` time for sufficiently regular patterns
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::game::*;
+
+/// Jumps shorter than this are quicker to just step through one generation at a time, as it takes
+/// a while to build the nodes in the first place
+pub const MIN_JUMP: usize = 1 << 10;
+/// Roughly how many words of a generation can be stepped through in the time it takes to work out
+/// a result which hasn't been memoised yet
+pub const WORD_STEPS_PER_RESULT: usize = 100;
+/// A jump which gives up is followed by stepping through this fraction of it before trying again
+pub const CHUNKS_PER_ATTEMPT: usize = 8;
+/// For when an engine's lock is poisoned
+pub const ENGINE_POISONED: &str = "Nothing should have panicked while using the engine";
+
+pub(crate) type NodeId = u32;
+
+/// A square of `2^level` by `2^level` cells
+#[derive(Copy, Clone, Debug)]
+struct Node {
+    level: u8,
+    /// `[nw, ne, sw, se]` quadrants, each of `level - 1`. Unused for leaves (`level == 0`).
+    children: [NodeId; 4],
+    population: u64,
+    /// Depends only on the cells, so it's the same for the same node in any engine
+    hash: u64,
+}
+
+pub(crate) struct HashLife {
+    rule: Rule,
+    nodes: Vec<Node>,
+    /// Every distinct node is only stored once
+    ids: HashMap<[NodeId; 4], NodeId>,
+    /// The centre quadrant of a node after `2^k` generations, keyed by the node and `k`
+    results: HashMap<(NodeId, u8), NodeId>,
+    /// The empty node of each level, indexed by level
+    empties: Vec<NodeId>,
+    /// Number of results which can still be worked out before giving up on the current jump, if
+    /// there's a limit
+    budget: Option<usize>,
+    /// The budget ran out during the current jump, so whatever it comes up with is wrong
+    gave_up: bool,
+    /// Number of jumps which it's given up on so far
+    give_ups: usize,
+}
+
+/// A pattern on an infinite plane. Clones share the same HashLife engine, so are cheap.
+/// Nodes are never changed once they've been made, so neither are any of the clones.
+///
+/// Rules where cells are born from nothing aren't supported, as they'd fill the whole plane.
+#[derive(Clone)]
+pub struct Universe {
+    engine: Arc<Mutex<HashLife>>,
+    root: NodeId,
+    /// Of the top-left corner of the root node
+    origin: Position,
+    index: usize,
+}
+
+impl HashLife {
+    const DEAD: NodeId = 0;
+    const ALIVE: NodeId = 1;
+    /// Start again with a fresh engine when advancing, rather than letting it grow any bigger
+    const MAX_NODES: usize = 1 << 22;
+
+    pub fn new(rule: Rule) -> Self {
+        let leaf = |population| Node {
+            level: 0,
+            children: [Self::DEAD; 4],
+            population,
+            hash: population,
+        };
+        Self {
+            rule,
+            nodes: vec![leaf(0), leaf(1)],
+            ids: HashMap::new(),
+            results: HashMap::new(),
+            empties: vec![Self::DEAD],
+            budget: None,
+            gave_up: false,
+            give_ups: 0,
+        }
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    /// Number of jumps which [`HashLife::nth_after`] has given up on so far
+    #[cfg(test)]
+    pub fn give_ups(&self) -> usize {
+        self.give_ups
+    }
+
+    /// Whether it's grown big enough that it should be replaced by a fresh engine
+    pub fn is_full(&self) -> bool {
+        self.nodes.len() > Self::MAX_NODES
+    }
+
+    pub fn level(&self, id: NodeId) -> u8 {
+        self.nodes[id as usize].level
+    }
+
+    pub fn population(&self, id: NodeId) -> u64 {
+        self.nodes[id as usize].population
+    }
+
+    /// Same as [`Generation::nth_after`], but a lot quicker for big enough `n` if the generation
+    /// is regular enough. The generation is treated as a torus by tiling it across an
+    /// otherwise-infinite plane. Gives up and returns `None` if more than `budget` results which
+    /// haven't been memoised yet turn out to be needed.
+    pub fn nth_after(&mut self, seed: &Generation, n: usize, budget: usize) -> Option<Generation> {
+        self.budget = Some(budget);
+        self.gave_up = false;
+        let mut generation = seed.clone();
+        let mut remaining = n;
+        while remaining != 0 && !self.gave_up {
+            // one jump per set bit, biggest first, but no bigger than the biggest node can manage
+            let log2_jump = (usize::BITS - 1 - remaining.leading_zeros()) as u8;
+            let log2_jump = log2_jump.min(Universe::MAX_LEVEL - 2);
+            generation = self.jump_torus(&generation, log2_jump);
+            remaining -= 1 << log2_jump;
+        }
+        self.budget = None;
+        if self.gave_up {
+            self.give_ups += 1;
+            None
+        } else {
+            Some(generation)
+        }
+    }
+
+    /// Advance a toroidal generation by `2^log2_jump` generations
+    fn jump_torus(&mut self, generation: &Generation, log2_jump: u8) -> Generation {
+        let (width, height) = (generation.width(), generation.height());
+        // the result must cover the whole torus, and the node must be big enough to jump that far
+        let size_log2 = ceil_log2(width.max(height));
+        let level = (size_log2 + 1).max(log2_jump + 2);
+
+        // the result of a node is its centre quadrant, so arrange for that to line up with (0, 0)
+        let margin = 1_isize << (level - 2);
+        let mut tiles = HashMap::new();
+        let root = self.tile(generation, &mut tiles, level, Position(-margin, -margin));
+        let result = self.result(root, log2_jump);
+
+        let mut next = generation.blank(generation.index() + (1 << log2_jump));
+        self.collect_cells(result, Position(0, 0), &mut next);
+        next
+    }
+
+    /// Build a node with its top-left corner at `position` of an infinite plane, tiled with copies
+    /// of `generation`. Every tile is identical, so only one node needs to be built for each level
+    /// and position within the tile, which are memoised in `tiles`.
+    fn tile(
+        &mut self,
+        generation: &Generation,
+        tiles: &mut HashMap<(u8, usize, usize), NodeId>,
+        level: u8,
+        position: Position,
+    ) -> NodeId {
+        if level == 0 {
+            return self.leaf(generation[position]);
+        }
+        let key = (
+            level,
+            position.x().rem_euclid(generation.width() as isize) as usize,
+            position.y().rem_euclid(generation.height() as isize) as usize,
+        );
+        if let Some(&id) = tiles.get(&key) {
+            return id;
+        }
+
+        let half = 1 << (level - 1);
+        let nw = self.tile(generation, tiles, level - 1, position);
+        let ne = self.tile(generation, tiles, level - 1, position + Position(half, 0));
+        let sw = self.tile(generation, tiles, level - 1, position + Position(0, half));
+        let se = self.tile(
+            generation,
+            tiles,
+            level - 1,
+            position + Position(half, half),
+        );
+        let id = self.join([nw, ne, sw, se]);
+        tiles.insert(key, id);
+        id
+    }
+
+    /// Bring the live cells of a node, with its top-left corner at `position`, to life in
+    /// `generation`. Cells which fall outside of the grid are ignored.
+    fn collect_cells(&self, id: NodeId, position: Position, generation: &mut Generation) {
+        let node = self.nodes[id as usize];
+        let (x, y) = (position.x(), position.y());
+        if node.population == 0
+            || x >= generation.width() as isize
+            || y >= generation.height() as isize
+        {
+            return;
+        }
+        let size = 1 << node.level;
+        if x + size <= 0 || y + size <= 0 {
+            return;
+        }
+        if node.level == 0 {
+            generation.set(position, Cell::Alive);
+            return;
+        }
+        let half = size / 2;
+        let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+        for (&child, &(dx, dy)) in node.children.iter().zip(offsets.iter()) {
+            self.collect_cells(child, position + Position(dx, dy), generation);
+        }
+    }
+
+    pub fn leaf(&self, cell: Cell) -> NodeId {
+        match cell {
+            Cell::Alive => Self::ALIVE,
+            Cell::Dead => Self::DEAD,
+        }
+    }
+
+    /// The canonical node with the given `[nw, ne, sw, se]` quadrants
+    pub fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&id) = self.ids.get(&children) {
+            return id;
+        }
+        let level = self.nodes[children[0] as usize].level + 1;
+        let population = children
+            .iter()
+            .map(|&child| self.nodes[child as usize].population)
+            .sum();
+        // cheap enough to not slow down building nodes, and good enough to not care about collisions
+        let hash = children.iter().fold(level as u64, |hash, &child| {
+            (hash.rotate_left(5) ^ self.nodes[child as usize].hash)
+                .wrapping_mul(0x517c_c1b7_2722_0a95)
+        });
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node {
+            level,
+            children,
+            population,
+            hash,
+        });
+        self.ids.insert(children, id);
+        id
+    }
+
+    /// The `[nw, ne, sw, se]` quadrants of a node
+    pub fn children(&self, id: NodeId) -> [NodeId; 4] {
+        self.nodes[id as usize].children
+    }
+
+    pub fn empty(&mut self, level: u8) -> NodeId {
+        while self.empties.len() <= level as usize {
+            let smaller = *self.empties.last().unwrap();
+            let empty = self.join([smaller; 4]);
+            self.empties.push(empty);
+        }
+        self.empties[level as usize]
+    }
+
+    /// A node of the given `level` with its top-left corner at `origin`, where `cells` are all of
+    /// the live cells within it
+    pub fn build(&mut self, level: u8, origin: Position, cells: &mut [Position]) -> NodeId {
+        if cells.is_empty() {
+            return self.empty(level);
+        }
+        if level == 0 {
+            return Self::ALIVE;
+        }
+        let half = 1 << (level - 1);
+        let centre = origin + Position(half, half);
+        let (north, south) = partition(cells, |position| position.y() < centre.y());
+        let (nw, ne) = partition(north, |position| position.x() < centre.x());
+        let (sw, se) = partition(south, |position| position.x() < centre.x());
+        let children = [
+            self.build(level - 1, origin, nw),
+            self.build(level - 1, origin + Position(half, 0), ne),
+            self.build(level - 1, origin + Position(0, half), sw),
+            self.build(level - 1, centre, se),
+        ];
+        self.join(children)
+    }
+
+    /// The cell at `position`, relative to the top-left corner of the node
+    pub fn cell(&self, id: NodeId, position: Position) -> Cell {
+        let (mut id, mut x, mut y) = (id, position.x(), position.y());
+        let size = 1 << self.level(id);
+        if x < 0 || y < 0 || x >= size || y >= size {
+            return Cell::Dead;
+        }
+        loop {
+            let node = self.nodes[id as usize];
+            if node.population == 0 {
+                return Cell::Dead;
+            }
+            if node.level == 0 {
+                return Cell::Alive;
+            }
+            let half = 1 << (node.level - 1);
+            let (east, south) = (x >= half, y >= half);
+            id = node.children[east as usize + 2 * south as usize];
+            x -= if east { half } else { 0 };
+            y -= if south { half } else { 0 };
+        }
+    }
+
+    /// Append the positions of the live cells of a node, with its top-left corner at `origin`, to
+    /// `cells`. If there are `bounds`, only cells from its top-left corner (inclusive) to its
+    /// bottom-right corner (exclusive) are included.
+    pub fn collect_live_cells(
+        &self,
+        id: NodeId,
+        origin: Position,
+        bounds: Option<(Position, Position)>,
+        cells: &mut Vec<Position>,
+    ) {
+        let node = self.nodes[id as usize];
+        if node.population == 0 {
+            return;
+        }
+        if let Some((min, max)) = bounds {
+            let size = 1 << node.level;
+            if origin.x() >= max.x()
+                || origin.y() >= max.y()
+                || origin.x() + size <= min.x()
+                || origin.y() + size <= min.y()
+            {
+                return;
+            }
+        }
+        if node.level == 0 {
+            cells.push(origin);
+            return;
+        }
+        let half = 1 << (node.level - 1);
+        let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+        for (&child, &(dx, dy)) in node.children.iter().zip(offsets.iter()) {
+            self.collect_live_cells(child, origin + Position(dx, dy), bounds, cells);
+        }
+    }
+
+    /// Number of cells which are alive in both `a` and `b`, where the top-left corner of `a` is at
+    /// `a_offset` from the top-left corner of `b`
+    pub fn common_population(&self, a: NodeId, b: NodeId, a_offset: Position) -> u64 {
+        let mut memo = HashMap::new();
+        self.common_population_at(a, self.level(a), a_offset, b, &mut memo)
+    }
+
+    /// Same as [`HashLife::common_population`], but where `a` is `level` big. It may be part of a
+    /// bigger node, so the level might not match the node's own.
+    fn common_population_at(
+        &self,
+        a: NodeId,
+        level: u8,
+        a_offset: Position,
+        b: NodeId,
+        memo: &mut HashMap<(NodeId, NodeId), u64>,
+    ) -> u64 {
+        if self.population(a) == 0 {
+            return 0;
+        }
+        if let Some(b) = self.node_at(b, level, a_offset) {
+            return self.common_population_aligned(a, b, memo);
+        }
+        // `b` doesn't have a node which lines up with `a`, so try again with smaller pieces
+        let half = 1 << (level - 1);
+        let offsets = [(0, 0), (half, 0), (0, half), (half, half)];
+        let children = self.children(a);
+        children
+            .iter()
+            .zip(offsets.iter())
+            .map(|(&child, &(dx, dy))| {
+                let child_offset = a_offset + Position(dx, dy);
+                self.common_population_at(child, level - 1, child_offset, b, memo)
+            })
+            .sum()
+    }
+
+    /// Same as [`HashLife::common_population`], but where `a` and `b` are exactly on top of
+    /// each other
+    fn common_population_aligned(
+        &self,
+        a: NodeId,
+        b: NodeId,
+        memo: &mut HashMap<(NodeId, NodeId), u64>,
+    ) -> u64 {
+        if self.population(a) == 0 || self.population(b) == 0 {
+            return 0;
+        }
+        if a == b {
+            return self.population(a);
+        }
+        if self.level(a) == 0 {
+            // both are alive
+            return 1;
+        }
+        if let Some(&common) = memo.get(&(a, b)) {
+            return common;
+        }
+        let common = self
+            .children(a)
+            .iter()
+            .zip(self.children(b).iter())
+            .map(|(&a, &b)| self.common_population_aligned(a, b, memo))
+            .sum();
+        memo.insert((a, b), common);
+        common
+    }
+
+    /// The descendant of a node which is `level` big, with its top-left corner at `position`
+    /// relative to the node's. [`HashLife::DEAD`] stands in for empty space if there isn't any
+    /// overlap, and it's `None` if there's only partial overlap.
+    fn node_at(&self, id: NodeId, level: u8, position: Position) -> Option<NodeId> {
+        let (size, node_size) = (1 << self.level(id), 1 << level);
+        let (x, y) = (position.x(), position.y());
+        if x >= size || y >= size || x + node_size <= 0 || y + node_size <= 0 {
+            return Some(Self::DEAD);
+        }
+        if x < 0 || y < 0 || x % node_size != 0 || y % node_size != 0 || level > self.level(id) {
+            return None;
+        }
+        let (mut id, mut x, mut y) = (id, x, y);
+        while self.level(id) > level {
+            let half = 1 << (self.level(id) - 1);
+            let (east, south) = (x >= half, y >= half);
+            id = self.children(id)[east as usize + 2 * south as usize];
+            x -= if east { half } else { 0 };
+            y -= if south { half } else { 0 };
+        }
+        Some(id)
+    }
+
+    /// Distance from the `near` side of a node to its closest live cell, or `None` if there aren't
+    /// any. `near` and `far` are the quadrants on either side, e.g. `[0, 2]` and `[1, 3]` for the
+    /// west side.
+    pub fn distance_to_live_cell(
+        &self,
+        id: NodeId,
+        near: [usize; 2],
+        far: [usize; 2],
+    ) -> Option<isize> {
+        let node = self.nodes[id as usize];
+        if node.population == 0 {
+            return None;
+        }
+        if node.level == 0 {
+            return Some(0);
+        }
+        let half = 1 << (node.level - 1);
+        let closest = |quadrants: [usize; 2]| {
+            quadrants
+                .iter()
+                .filter_map(|&quadrant| {
+                    self.distance_to_live_cell(node.children[quadrant], near, far)
+                })
+                .min()
+        };
+        closest(near).or_else(|| closest(far).map(|distance| distance + half))
+    }
+
+    /// A copy of a node where the cell at `position`, relative to its top-left corner, is `cell`
+    fn set(&mut self, id: NodeId, position: Position, cell: Cell) -> NodeId {
+        let level = self.level(id);
+        if level == 0 {
+            return self.leaf(cell);
+        }
+        let half = 1 << (level - 1);
+        let (east, south) = (position.x() >= half, position.y() >= half);
+        let quadrant = east as usize + 2 * south as usize;
+        let offset = Position(-half * east as isize, -half * south as isize);
+        let mut children = self.children(id);
+        children[quadrant] = self.set(children[quadrant], position + offset, cell);
+        self.join(children)
+    }
+
+    /// Surround a node with empty space, making it twice as big. Returns the new node and the
+    /// position of its top-left corner, given the position of the old node's.
+    fn expand(&mut self, id: NodeId, origin: Position) -> (NodeId, Position) {
+        let level = self.level(id);
+        assert!(
+            level < Universe::MAX_LEVEL,
+            "Patterns can't spread any further across the plane than positions can reach"
+        );
+        let [nw, ne, sw, se] = self.children(id);
+        let empty = self.empty(level - 1);
+        let children = [
+            self.join([empty, empty, empty, nw]),
+            self.join([empty, empty, ne, empty]),
+            self.join([empty, sw, empty, empty]),
+            self.join([se, empty, empty, empty]),
+        ];
+        let half = 1 << (level - 1);
+        (self.join(children), origin + Position(-half, -half))
+    }
+
+    /// Whether all of the live cells of a node are within its middle half (in both directions)
+    fn is_padded(&mut self, id: NodeId) -> bool {
+        let centre = self.centre(id);
+        self.population(centre) == self.population(id)
+    }
+
+    /// Recreate a node from another engine in this one
+    fn import(&mut self, other: &Self, id: NodeId, memo: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if other.level(id) == 0 {
+            return id;
+        }
+        if let Some(&imported) = memo.get(&id) {
+            return imported;
+        }
+        let mut children = other.children(id);
+        for child in &mut children {
+            *child = self.import(other, *child, memo);
+        }
+        let imported = self.join(children);
+        memo.insert(id, imported);
+        imported
+    }
+
+    /// The middle of a node, half its size
+    fn centre(&mut self, id: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(id);
+        self.join([
+            self.children(nw)[3],
+            self.children(ne)[2],
+            self.children(sw)[1],
+            self.children(se)[0],
+        ])
+    }
+
+    /// The node straddling the border between two horizontally adjacent nodes
+    fn centre_horizontal(&mut self, west: NodeId, east: NodeId) -> NodeId {
+        let [_, w_ne, _, w_se] = self.children(west);
+        let [e_nw, _, e_sw, _] = self.children(east);
+        self.join([w_ne, e_nw, w_se, e_sw])
+    }
+
+    /// The node straddling the border between two vertically adjacent nodes
+    fn centre_vertical(&mut self, north: NodeId, south: NodeId) -> NodeId {
+        let [_, _, n_sw, n_se] = self.children(north);
+        let [s_nw, s_ne, _, _] = self.children(south);
+        self.join([n_sw, n_se, s_nw, s_ne])
+    }
+
+    /// The centre quadrant of a node after `2^log2_jump` generations, where the node must be at
+    /// least of level `log2_jump + 2`
+    fn result(&mut self, id: NodeId, log2_jump: u8) -> NodeId {
+        if let Some(&result) = self.results.get(&(id, log2_jump)) {
+            return result;
+        }
+        match &mut self.budget {
+            Some(0) => self.gave_up = true,
+            Some(budget) => *budget -= 1,
+            None => {}
+        }
+        if self.gave_up {
+            // anything of the right level will do, as it's only going to be thrown away
+            return self.children(id)[0];
+        }
+        let level = self.nodes[id as usize].level;
+        debug_assert!(level >= 2 && log2_jump <= level - 2);
+
+        let result = if self.nodes[id as usize].population == 0 && !self.rule.births_from_nothing()
+        {
+            // nothing can happen
+            self.children(id)[0]
+        } else if level == 2 {
+            self.result_of_smallest(id)
+        } else {
+            let [nw, ne, sw, se] = self.children(id);
+            let n = self.centre_horizontal(nw, ne);
+            let w = self.centre_vertical(nw, sw);
+            let c = self.centre(id);
+            let e = self.centre_vertical(ne, se);
+            let s = self.centre_horizontal(sw, se);
+            let overlapping = [nw, n, ne, w, c, e, sw, s, se];
+
+            // the jump is split into two halves when going at full speed, otherwise the first
+            // half is skipped and the whole jump is done in the second half
+            let full_speed = log2_jump == level - 2;
+            let mut partial = [Self::DEAD; 9];
+            for (partial, &node) in partial.iter_mut().zip(overlapping.iter()) {
+                *partial = if full_speed {
+                    self.result(node, log2_jump - 1)
+                } else {
+                    self.centre(node)
+                };
+            }
+            let second_jump = if full_speed { log2_jump - 1 } else { log2_jump };
+            let [p_nw, p_n, p_ne, p_w, p_c, p_e, p_sw, p_s, p_se] = partial;
+            let quadrants = [
+                self.join([p_nw, p_n, p_w, p_c]),
+                self.join([p_n, p_ne, p_c, p_e]),
+                self.join([p_w, p_c, p_sw, p_s]),
+                self.join([p_c, p_e, p_s, p_se]),
+            ];
+            let mut results = [Self::DEAD; 4];
+            for (result, &quadrant) in results.iter_mut().zip(quadrants.iter()) {
+                *result = self.result(quadrant, second_jump);
+            }
+            self.join(results)
+        };
+        if !self.gave_up {
+            self.results.insert((id, log2_jump), result);
+        }
+        result
+    }
+
+    /// The centre 2x2 cells of a 4x4 node after one generation
+    fn result_of_smallest(&mut self, id: NodeId) -> NodeId {
+        let mut cells = [[Cell::Dead; 4]; 4];
+        for (quadrant, &child) in self.children(id).iter().enumerate() {
+            for (idx, &leaf) in self.children(child).iter().enumerate() {
+                let x = (quadrant % 2) * 2 + idx % 2;
+                let y = (quadrant / 2) * 2 + idx / 2;
+                cells[y][x] = if leaf == Self::ALIVE {
+                    Cell::Alive
+                } else {
+                    Cell::Dead
+                };
+            }
+        }
+
+        let mut next = [Self::DEAD; 4];
+        for (idx, next) in next.iter_mut().enumerate() {
+            let (x, y) = (1 + idx % 2, 1 + idx / 2);
+            let live_neighbour_count = cells[y - 1..=y + 1]
+                .iter()
+                .flat_map(|row| &row[x - 1..=x + 1])
+                .filter(|cell| cell.is_alive())
+                .count()
+                - cells[y][x].is_alive() as usize;
+            *next = self.leaf(self.rule.next_state(cells[y][x], live_neighbour_count));
+        }
+        self.join(next)
+    }
+}
+
+impl fmt::Debug for Universe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Universe")
+            .field("origin", &self.origin)
+            .field("index", &self.index)
+            .field("population", &self.population())
+            .finish()
+    }
+}
+
+impl Hash for Universe {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.origin.hash(state);
+        self.engine().nodes[self.root as usize].hash.hash(state);
+    }
+}
+
+impl Universe {
+    /// Root nodes are never any smaller than this, so they can always be split into 8x8 blocks
+    pub const MIN_LEVEL: u8 = 3;
+    /// Root nodes are never any bigger than this, so that every cell of them has a [`Position`]
+    pub const MAX_LEVEL: u8 = 62;
+    /// Root nodes which come from elsewhere can't be any bigger than this, so that there's still
+    /// room to surround them with empty space before stepping them
+    pub const MAX_SEED_LEVEL: u8 = Self::MAX_LEVEL - 2;
+    /// Cells of a seed can be this far from `(0, 0)` in either direction, and still fit in a root
+    /// node no bigger than [`Universe::MAX_SEED_LEVEL`]
+    pub const MAX_SEED_DISTANCE: isize = 1 << (Self::MAX_SEED_LEVEL - 2);
+
+    pub fn new(index: usize, rule: Rule, cells: &[Position]) -> Self {
+        let min_x = cells.iter().map(Position::x).min().unwrap_or(0);
+        let min_y = cells.iter().map(Position::y).min().unwrap_or(0);
+        let max_x = cells.iter().map(Position::x).max().unwrap_or(0);
+        let max_y = cells.iter().map(Position::y).max().unwrap_or(0);
+        let size = (max_x - min_x).max(max_y - min_y) as usize + 1;
+        let level = ceil_log2(size).max(Self::MIN_LEVEL);
+
+        let mut engine = HashLife::new(rule);
+        let origin = Position(min_x, min_y);
+        let root = engine.build(level, origin, &mut cells.to_vec());
+        Self::from_root(engine, root, origin, index)
+    }
+
+    /// `origin` is the position of the top-left corner of the `root` node
+    pub(crate) fn from_root(
+        engine: HashLife,
+        root: NodeId,
+        origin: Position,
+        index: usize,
+    ) -> Self {
+        Self {
+            engine: Arc::new(Mutex::new(engine)),
+            root,
+            origin,
+            index,
+        }
+    }
+
+    /// Same as [`Generation::nth_after`], but for an infinite plane
+    pub fn nth_after(seed: &Self, n: usize) -> Self {
+        let mut universe = seed.clone();
+        if universe.engine().is_full() {
+            universe.compact();
+        }
+
+        let mut engine = universe.engine();
+        let (mut root, mut origin) = (universe.root, universe.origin);
+        let mut remaining = n;
+        while remaining != 0 {
+            // one jump per set bit, biggest first, but no bigger than the biggest root can manage
+            let log2_jump = (usize::BITS - 1 - remaining.leading_zeros()) as u8;
+            let log2_jump = log2_jump.min(Self::MAX_LEVEL - 3);
+            // make sure there's enough empty space around the pattern that nothing can escape the
+            // centre quadrant, which is all that's left after the jump, and that what's left is
+            // still big enough to be a root
+            let min_level = (log2_jump + 3).max(Self::MIN_LEVEL + 1);
+            loop {
+                let centre = engine.centre(root);
+                if engine.level(root) >= min_level
+                    && engine.is_padded(root)
+                    && engine.is_padded(centre)
+                {
+                    break;
+                }
+                let (expanded, expanded_origin) = engine.expand(root, origin);
+                root = expanded;
+                origin = expanded_origin;
+            }
+            let quarter = 1 << (engine.level(root) - 2);
+            root = engine.result(root, log2_jump);
+            origin += Position(quarter, quarter);
+            remaining -= 1 << log2_jump;
+        }
+        // don't let the root keep growing with every jump
+        while engine.level(root) > Self::MIN_LEVEL && engine.is_padded(root) {
+            let quarter = 1 << (engine.level(root) - 2);
+            root = engine.centre(root);
+            origin += Position(quarter, quarter);
+        }
+        drop(engine);
+
+        universe.root = root;
+        universe.origin = origin;
+        universe.index += n;
+        universe
+    }
+
+    /// Bring the cell at `position` to life or kill it, growing the universe if need be
+    pub fn set(&mut self, position: Position, cell: Cell) {
+        let mut engine = self.engine();
+        let (mut root, mut origin) = (self.root, self.origin);
+        loop {
+            let size = 1 << engine.level(root);
+            let relative = position + Position(-origin.x(), -origin.y());
+            if (0..size).contains(&relative.x()) && (0..size).contains(&relative.y()) {
+                root = engine.set(root, relative, cell);
+                break;
+            }
+            let (expanded, expanded_origin) = engine.expand(root, origin);
+            root = expanded;
+            origin = expanded_origin;
+        }
+        drop(engine);
+
+        self.root = root;
+        self.origin = origin;
+    }
+
+    /// The same cells moved by `offset`, as generation `index`
+    pub fn moved(&self, offset: Position, index: usize) -> Self {
+        Self {
+            origin: self.origin + offset,
+            index,
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn engine(&self) -> MutexGuard<'_, HashLife> {
+        self.engine.lock().expect(ENGINE_POISONED)
+    }
+
+    pub(crate) fn root(&self) -> NodeId {
+        self.root
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.engine().rule()
+    }
+
+    pub fn population(&self) -> u64 {
+        let engine = self.engine();
+        engine.population(self.root)
+    }
+
+    /// Number of cells which are alive in both this universe and the `other` one
+    pub fn common_population(&self, other: &Self) -> u64 {
+        let mut engine = self.engine();
+        let other_root = if Arc::ptr_eq(&self.engine, &other.engine) {
+            other.root
+        } else {
+            engine.import(&other.engine(), other.root, &mut HashMap::new())
+        };
+        let offset = self.origin + Position(-other.origin.x(), -other.origin.y());
+        engine.common_population(self.root, other_root, offset)
+    }
+
+    /// Top-left corner (inclusive) and bottom-right corner (exclusive) of the smallest rectangle
+    /// which contains every live cell, or `None` if there aren't any
+    pub fn bounds(&self) -> Option<(Position, Position)> {
+        let engine = self.engine();
+        let size = 1 << engine.level(self.root);
+        let west = engine.distance_to_live_cell(self.root, [0, 2], [1, 3])?;
+        let east = engine.distance_to_live_cell(self.root, [1, 3], [0, 2])?;
+        let north = engine.distance_to_live_cell(self.root, [0, 1], [2, 3])?;
+        let south = engine.distance_to_live_cell(self.root, [2, 3], [0, 1])?;
+        Some((
+            self.origin + Position(west, north),
+            self.origin + Position(size - east, size - south),
+        ))
+    }
+
+    pub fn cell(&self, position: Position) -> Cell {
+        let relative = position + Position(-self.origin.x(), -self.origin.y());
+        self.engine().cell(self.root, relative)
+    }
+
+    /// Positions of the live cells from the top-left corner of the `bounds` (inclusive) to the
+    /// bottom-right corner (exclusive), or of every live cell if there are no `bounds`
+    pub fn live_cells(&self, bounds: Option<(Position, Position)>) -> Vec<Position> {
+        let mut cells = Vec::new();
+        self.engine()
+            .collect_live_cells(self.root, self.origin, bounds, &mut cells);
+        cells
+    }
+
+    /// Move to a fresh engine which only contains this universe's nodes, leaving behind the
+    /// nodes and memoised results of every other universe which shared the old one
+    fn compact(&mut self) {
+        let old = self.engine();
+        let mut engine = HashLife::new(old.rule());
+        let root = engine.import(&old, self.root, &mut HashMap::new());
+        drop(old);
+        self.engine = Arc::new(Mutex::new(engine));
+        self.root = root;
+    }
+}
+
+/// Reorder `cells` so that those matching the `predicate` come first, then split them
+fn partition<P>(cells: &mut [Position], predicate: P) -> (&mut [Position], &mut [Position])
+where
+    P: Fn(&Position) -> bool,
+{
+    let mut split = 0;
+    for idx in 0..cells.len() {
+        if predicate(&cells[idx]) {
+            cells.swap(split, idx);
+            split += 1;
+        }
+    }
+    cells.split_at_mut(split)
+}
+
+/// Smallest `n` where `2^n >= x`
+fn ceil_log2(x: usize) -> u8 {
+    (usize::BITS - x.saturating_sub(1).leading_zeros()) as u8
+}
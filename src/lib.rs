@@ -0,0 +1,14 @@
+//! Simulation of Life-like cellular automata: building, stepping and indexing a [`Generation`], and
+//! reading and writing them as patterns. The terminal front-end and the app which runs in it are
+//! behind the `tui` feature.
+//!
+//! [`Generation`]: game::Generation
+
+#[cfg(feature = "tui")]
+pub mod app;
+#[cfg(feature = "tui")]
+pub mod display;
+pub mod export;
+pub mod game;
+mod hashlife;
+pub mod pattern;
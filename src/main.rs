@@ -1,17 +1,22 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process;
 use std::time::Duration;
 
-use rand::distributions::{Bernoulli, BernoulliError};
-use rand::rngs::SmallRng;
-use rand::{Rng, SeedableRng};
+use structopt::clap::{self, ErrorKind};
 use structopt::StructOpt;
 
-use crate::game::Cell;
+use conways_game_of_life::app;
+use conways_game_of_life::display::{GifDisplay, Glyphs, HeadlessDisplay, TerminalDisplay};
+use conways_game_of_life::export::{asciicast, gif, Colour, ImageFormat, Theme};
+use conways_game_of_life::game::{Position, Rule, Topology, Universe};
+use conways_game_of_life::pattern::{self, Format};
 
-mod display;
-mod game;
-
-const FALLBACK_WIDTH: usize = 40;
-const FALLBACK_HEIGHT: usize = 20;
+/// Exit status of a `--headless` run which stopped early because of `--stop-on-stable`
+const EXIT_STABLE: i32 = 3;
+/// Exit status when the options couldn't be parsed, or don't make sense together
+const EXIT_USAGE: i32 = 2;
 
 #[derive(StructOpt, Debug)]
 #[structopt()]
@@ -29,6 +34,40 @@ struct CliOptions {
     )]
     weight: f32,
 
+    #[structopt(
+        long,
+        help = "Rulestring in B/S or S/B notation, e.g. B36/S23 [default: the pattern's rule, or B3/S23]"
+    )]
+    rule: Option<Rule>,
+
+    #[structopt(
+        long,
+        default_value = "torus",
+        help = "How the edges of the grid join up: torus, plane, klein, cross-surface or unbounded"
+    )]
+    topology: Topology,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Pattern file (RLE, plaintext, Life 1.05/1.06 or macrocell) to load the first generation from"
+    )]
+    pattern: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        allow_hyphen_values = true,
+        help = "Position of the top-left corner of the pattern, e.g. 12,-5 [default: centred]"
+    )]
+    offset: Option<Position>,
+
+    #[structopt(
+        long,
+        default_value = "rle",
+        help = "Format to save generations in when pressing 'p' or with --headless: rle, cells, life105, life106 or mc"
+    )]
+    save_format: Format,
+
     #[structopt(
         short,
         long,
@@ -48,6 +87,98 @@ struct CliOptions {
     #[structopt(short, long, help = "Number of generations to display [default: ∞]")]
     count: Option<usize>,
 
+    #[structopt(
+        long,
+        help = "Start paused, so that only the first generation is displayed"
+    )]
+    paused: bool,
+
+    #[structopt(
+        long,
+        conflicts_with = "restart-on-stable",
+        help = "Stop once the generations start repeating"
+    )]
+    stop_on_stable: bool,
+
+    #[structopt(long, help = "Restart once the generations start repeating")]
+    restart_on_stable: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["restart-on-stable", "paused"],
+        help = "Don't display anything, just write the last generation in the --save-format"
+    )]
+    headless: bool,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        requires = "headless",
+        help = "File to write the last generation to with --headless [default: stdout]"
+    )]
+    output: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        help = "Record each generation which is displayed as a frame of an animated GIF, shown for --period each"
+    )]
+    export_gif: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with = "headless",
+        help = "Record everything drawn to the terminal as an asciicast v2 file, to replay with asciinema"
+    )]
+    record: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        default_value = "4",
+        help = "Width and height of each cell in exported images (in pixels)"
+    )]
+    cell_size: usize,
+
+    #[structopt(
+        long,
+        default_value = "#ffffff",
+        help = "Colour of live cells in exported images"
+    )]
+    alive_colour: Colour,
+
+    #[structopt(
+        long,
+        default_value = "#000000",
+        help = "Colour of dead cells in exported images"
+    )]
+    dead_colour: Colour,
+
+    #[structopt(
+        long,
+        help = "Colour of the lines between cells in exported images [default: no lines]"
+    )]
+    grid_colour: Option<Colour>,
+
+    #[structopt(
+        long,
+        help = "Index of a generation to save an image of, or of the first one after it with --step"
+    )]
+    snapshot_at: Option<usize>,
+
+    #[structopt(
+        long,
+        default_value = "png",
+        help = "Format to save images in when pressing 'i' or with --snapshot-at: png, ppm, pbm or svg"
+    )]
+    image_format: ImageFormat,
+
+    #[structopt(
+        long,
+        help = "Write the index of the generation below it in SVG images"
+    )]
+    caption: bool,
+
     #[structopt(
         short,
         long,
@@ -56,268 +187,194 @@ struct CliOptions {
     )]
     period: u64,
 
+    #[structopt(
+        long,
+        default_value = "block",
+        help = "Characters to draw cells with: block (1 cell each), half (2 cells each) or braille (8 cells each)"
+    )]
+    glyphs: Glyphs,
+
+    #[cfg(feature = "parallel")]
+    #[structopt(
+        long,
+        default_value = "1",
+        help = "Number of threads to step each generation with, in bands of rows"
+    )]
+    threads: usize,
+
     #[structopt(
         short,
         long,
-        help = "Number of horizontal cells to simulate [default: terminal-width]"
+        help = "Number of horizontal cells to simulate [default: terminal-width, or with room for the --pattern to move around in with --headless]"
     )]
     width: Option<usize>,
 
     #[structopt(
         short,
         long,
-        help = "Number of vertical cells to simulate [default: terminal-height]"
+        help = "Number of vertical cells to simulate [default: terminal-height, or with room for the --pattern to move around in with --headless]"
     )]
     height: Option<usize>,
 }
 
 fn main() -> app::Result<()> {
-    let cli_opts: CliOptions = CliOptions::from_args();
+    let matches = CliOptions::clap()
+        .get_matches_safe()
+        .unwrap_or_else(|err| match err.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => err.exit(),
+            _ => exit_with_usage_error(err),
+        });
+    let cli_opts = CliOptions::from_clap(&matches);
 
     let preferred_size = match (cli_opts.width, cli_opts.height) {
+        (Some(0), Some(_)) | (Some(_), Some(0)) => {
+            bad_args("--width and --height must be at least 1, so that there's a cell to simulate")
+        }
         (Some(w), Some(h)) => Some((w, h)),
         (None, None) => None,
-        // TODO: better error handling than for bad user input
-        (_, _) => panic!("Bad user args: Must provide exactly 0 or 2 of: [width, height]"),
+        (_, _) => bad_args("Must provide both or neither of --width and --height"),
     };
-
-    // TODO: better error handing for user-supplied weight outside [0.0, 1.0]
-    let cell_gen = weighted_cell_generator(cli_opts.weight, cli_opts.seed)
-        .expect("Bad user args: Weight must be in the range [0.0, 1.0]");
-
-    let app = app::App::new(
-        cli_opts.start,
-        cli_opts.step,
-        cli_opts.count.unwrap_or(usize::MAX),
-        preferred_size,
-        Duration::from_millis(cli_opts.period),
-        cell_gen,
-    )?;
-
-    app.run_to_completion()?;
-
-    Ok(())
-}
-
-fn weighted_cell_generator(
-    weight: f32,
-    seed: Option<u64>,
-) -> Result<impl FnMut() -> Cell, BernoulliError> {
-    let distr = Bernoulli::new(weight.into())?;
-    let rng = seed
-        .map(SmallRng::seed_from_u64)
-        .unwrap_or_else(SmallRng::from_entropy);
-    let mut cell_iter =
-        rng.sample_iter::<bool, _>(distr)
-            .map(|alive| if alive { Cell::Alive } else { Cell::Dead });
-    Ok(move || {
-        cell_iter
-            .next()
-            .expect("Expected Rng::sample_iter(Distribution) to be infinite")
-    })
-}
-
-mod app {
-    use std::time::Duration;
-    use std::{fmt, thread};
-
-    use crate::display::*;
-    use crate::game::*;
-
-    #[derive(Debug)]
-    pub enum Error {
-        Display(crossterm::ErrorKind),
-    }
-
-    pub type Result<T> = std::result::Result<T, Error>;
-
-    #[derive(Copy, Clone, Eq, PartialEq, Debug)]
-    pub enum Action {
-        Restart,
-        Exit,
-        Unmapped,
+    if cli_opts.count == Some(0) {
+        bad_args("--count must be at least 1, as the first generation is always displayed");
     }
 
-    #[derive(Clone, Eq, PartialEq, Debug)]
-    enum State {
-        Initial,
-        Running,
-        Waiting(Duration, Box<State>),
-        Finished,
-    }
-
-    pub struct App<F>
-    where
-        F: FnMut() -> Cell,
-    {
-        start: usize,
-        step: usize,
-        count: usize,
-        curr_count: usize,
-        size: (usize, usize),
-        period: Duration,
-        cell_gen: F,
-        state: State,
-        generation: Generation,
-        display: TerminalDisplay,
+    let max_offset = Universe::MAX_SEED_DISTANCE;
+    if let Some(offset) = cli_opts.offset {
+        let range = -max_offset..=max_offset;
+        if !range.contains(&offset.x()) || !range.contains(&offset.y()) {
+            bad_args(&format!(
+                "--offset must be at most {} from 0,0 in either direction",
+                max_offset
+            ));
+        }
     }
 
-    impl<F> App<F>
-    where
-        F: FnMut() -> Cell,
-    {
-        pub fn new(
-            start: usize,
-            step: usize,
-            count: usize,
-            preferred_size: Option<(usize, usize)>,
-            period: Duration,
-            mut cell_gen: F,
-        ) -> Result<Self> {
-            let display = TerminalDisplay::new().map_err(Error::from)?;
-            let (width, height) = preferred_size
-                .or_else(|| {
-                    display
-                        .available_cells()
-                        .map(|(width, height)| (width as usize, height as usize))
-                })
-                .unwrap_or_else(|| (super::FALLBACK_WIDTH, super::FALLBACK_HEIGHT));
-
-            let seed_gen = Generation::generate(0, width, height, &mut cell_gen);
-            let generation = Generation::nth_after(&seed_gen, start);
-            Ok(Self {
-                start,
-                step,
-                count: count - 1,
-                curr_count: count - 1,
-                size: (width, height),
-                period,
-                cell_gen,
-                state: State::Initial,
-                generation,
-                display,
-            })
+    let (seed, pattern_rule) = match &cli_opts.pattern {
+        Some(path) => {
+            let pattern = pattern::read(path)?;
+            let pattern_rule = pattern.rule();
+            (app::Seed::Pattern(pattern, cli_opts.offset), pattern_rule)
         }
-
-        pub fn run_to_completion(mut self) -> Result<()> {
-            loop {
-                self.handle_input()?;
-                match self.state.clone() {
-                    State::Initial => {
-                        self.render()?;
-                        self.state = State::Waiting(self.period, Box::new(State::Running));
-                    }
-                    State::Running => {
-                        self.update()?;
-                        self.render()?;
-                        self.state = State::Waiting(self.period, Box::new(self.state.clone()));
-                    }
-                    State::Waiting(duration, next_state) => {
-                        self.wait(duration, next_state);
-                    }
-                    State::Finished => {
-                        break;
-                    }
-                }
-            }
-            Ok(())
+        None => {
+            // pick a seed now rather than letting the PRNG seed itself, so it can be saved later
+            let seed = cli_opts.seed.unwrap_or_else(rand::random);
+            let seed = app::Seed::random(seed, cli_opts.weight)
+                .unwrap_or_else(|_| bad_args("--weight must be in the range [0.0, 1.0]"));
+            (seed, None)
         }
+    };
 
-        fn handle_input(&mut self) -> Result<()> {
-            let mut latest_action = Action::Unmapped;
-            while let Some(ev) = self.display.take_pending_event()? {
-                let action = Action::from(ev);
-                if action != Action::Unmapped {
-                    latest_action = action;
-                }
-            }
-            match latest_action {
-                Action::Restart => {
-                    let (width, height) = self.size;
-                    let seed_gen = Generation::generate(0, width, height, &mut self.cell_gen);
-                    self.generation = Generation::nth_after(&seed_gen, self.start);
-                    self.curr_count = self.count;
-                    self.state = State::Initial;
-                }
-                Action::Exit => {
-                    self.state = State::Finished;
-                }
-                Action::Unmapped => {}
-            }
-            Ok(())
-        }
+    let rule = cli_opts.rule.or(pattern_rule).unwrap_or_default();
+    if cli_opts.topology == Topology::Unbounded && rule.births_from_nothing() {
+        bad_args("Rules with B0 would fill the whole of an unbounded universe");
+    }
 
-        fn update(&mut self) -> Result<()> {
-            if self.curr_count != 0 {
-                self.curr_count -= 1;
-                self.generation = Generation::nth_after(&self.generation, self.step);
-            } else {
-                self.state = State::Finished;
-            }
-            Ok(())
+    let theme = Theme {
+        cell_size: cli_opts.cell_size,
+        alive: cli_opts.alive_colour,
+        dead: cli_opts.dead_colour,
+        grid: cli_opts.grid_colour,
+        caption: cli_opts.caption,
+    };
+    if theme.cell_size <= theme.grid.is_some() as usize {
+        bad_args("Cells must be at least 1 pixel big, not including any grid lines");
+    }
+    let gif = match &cli_opts.export_gif {
+        Some(path) => {
+            let out = BufWriter::new(File::create(path).map_err(app::Error::Export)?);
+            Some(gif::Encoder::new(out, theme))
         }
-
-        fn render(&mut self) -> Result<()> {
-            self.display.draw(&self.generation).map_err(Error::from)
+        None => None,
+    };
+    let gif_delay = Duration::from_millis(cli_opts.period);
+
+    let config = app::Config {
+        start: cli_opts.start,
+        step: cli_opts.step,
+        count: cli_opts.count.unwrap_or(usize::MAX),
+        paused: cli_opts.paused,
+        on_stable: match (cli_opts.stop_on_stable, cli_opts.restart_on_stable) {
+            (true, _) => app::OnStable::Stop,
+            (_, true) => app::OnStable::Restart,
+            (false, false) => app::OnStable::Continue,
+        },
+        preferred_size,
+        period: Duration::from_millis(cli_opts.period),
+        rule,
+        topology: cli_opts.topology,
+        save_format: cli_opts.save_format,
+        theme,
+        image_format: cli_opts.image_format,
+        snapshot_at: cli_opts.snapshot_at,
+        glyphs: cli_opts.glyphs,
+        #[cfg(feature = "parallel")]
+        threads: cli_opts.threads,
+    };
+    if cli_opts.headless {
+        if cli_opts.count.is_none() && !cli_opts.stop_on_stable {
+            bad_args("Must provide --count or --stop-on-stable with --headless");
         }
-
-        fn wait(&mut self, duration: Duration, next_state: Box<State>) {
-            /// Max time to sleep without checking for and handling any buffered user input
-            static MAX_WAIT_TIME: Duration = Duration::from_millis(250);
-
-            // could sleep for longer than `duration` but the inaccuracy shouldn't be enough to care
-            // about right now
-            if duration > MAX_WAIT_TIME {
-                thread::sleep(MAX_WAIT_TIME);
-                self.state = State::Waiting(duration - MAX_WAIT_TIME, next_state);
-            } else {
-                thread::sleep(duration);
-                self.state = *next_state;
+        let output = cli_opts.output.as_deref();
+        let outcome = match gif {
+            Some(gif) => {
+                let display = GifDisplay::new(HeadlessDisplay, gif, gif_delay);
+                app::run_headless(config, seed, display, output)
             }
+            None => app::run_headless(config, seed, HeadlessDisplay, output),
+        };
+        let outcome = outcome.map_err(|err| grid_too_big(err, preferred_size))?;
+        if outcome == app::Outcome::Stable {
+            process::exit(EXIT_STABLE);
         }
+        return Ok(());
     }
 
-    impl From<Event> for Action {
-        fn from(ev: Event) -> Self {
-            match ev {
-                Event::Key(key_ev) => Self::from(key_ev),
-                _ => Self::Unmapped,
-            }
-        }
+    let mut display = TerminalDisplay::new(config.glyphs)?;
+    if let Some(path) = &cli_opts.record {
+        let out = BufWriter::new(File::create(path).map_err(app::Error::Export)?);
+        let size = crossterm::terminal::size()?;
+        let recorder = asciicast::Recorder::new(out, size).map_err(app::Error::Export)?;
+        display = display.with_recording(recorder)?;
     }
-
-    impl From<KeyEvent> for Action {
-        fn from(key_ev: KeyEvent) -> Self {
-            match key_ev.code {
-                KeyCode::Char('r') => Self::Restart,
-                KeyCode::Char('q') | KeyCode::Esc => Self::Exit,
-                KeyCode::Char('c') if key_ev.modifiers.contains(KeyModifiers::CONTROL) => {
-                    Self::Exit
-                }
-                _ => Self::Unmapped,
-            }
+    match gif {
+        Some(gif) => {
+            let display = GifDisplay::new(display, gif, gif_delay);
+            app::App::new(config, seed, display)
+                .map_err(|err| grid_too_big(err, preferred_size))?
+                .run_to_completion()?;
         }
+        None => app::App::new(config, seed, display)
+            .map_err(|err| grid_too_big(err, preferred_size))?
+            .run_to_completion()?,
     }
 
-    impl std::error::Error for Error {
-        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-            match self {
-                Self::Display(err) => Some(err),
-            }
-        }
-    }
+    Ok(())
+}
 
-    impl fmt::Display for Error {
-        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-            match self {
-                Self::Display(err) => fmt::Display::fmt(err, f),
-            }
+/// Report a grid which is too big as a problem with the options which sized it, and pass any other
+/// error through
+fn grid_too_big(err: app::Error, preferred_size: Option<(usize, usize)>) -> app::Error {
+    match (err, preferred_size) {
+        (app::Error::GridTooBig(err), Some(_)) => {
+            bad_args(&format!("--width and --height are too big: {}", err))
         }
+        (app::Error::GridTooBig(err), None) => bad_args(&format!(
+            "The --pattern is too big to give room around: {}. Provide --width and --height, or use --topology unbounded",
+            err
+        )),
+        (err, _) => err,
     }
+}
 
-    impl From<crossterm::ErrorKind> for Error {
-        fn from(source: crossterm::ErrorKind) -> Self {
-            Self::Display(source)
-        }
-    }
+/// Options which were parsed fine, but don't make sense together
+fn bad_args(description: &str) -> ! {
+    exit_with_usage_error(clap::Error::with_description(
+        description,
+        ErrorKind::ArgumentConflict,
+    ))
+}
+
+fn exit_with_usage_error(err: clap::Error) -> ! {
+    eprintln!("{}", err.message);
+    process::exit(EXIT_USAGE)
 }
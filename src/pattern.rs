@@ -0,0 +1,396 @@
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::{error, fmt};
+
+use crate::game::*;
+
+pub mod life;
+pub mod macrocell;
+pub mod plaintext;
+pub mod rle;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Couldn't work out the format from the file's header or extension
+    UnknownFormat,
+    /// The file ended before the pattern was complete
+    UnexpectedEof,
+    /// The 1-based line number and a description of what was wrong with it
+    Syntax(usize, String),
+    Rule(ParseRuleError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Largest width or height of a pattern which lists its cells one by one, so that a typo can't ask
+/// for more cells than could fit in memory. Bigger patterns are better off as macrocells anyway.
+const MAX_SIZE: usize = 1 << 16;
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Format {
+    /// `.rle`
+    Rle,
+    /// `.cells`
+    Plaintext,
+    /// `.lif`, `.life`, with a `#Life 1.05` header
+    Life105,
+    /// `.lif`, `.life`, with a `#Life 1.06` header
+    Life106,
+    /// `.mc`
+    Macrocell,
+}
+
+/// The live cells of a pattern, along with any metadata which came with it
+#[derive(Clone, Default, Debug)]
+pub struct Pattern {
+    name: Option<String>,
+    author: Option<String>,
+    comments: Vec<String>,
+    rule: Option<Rule>,
+    width: usize,
+    height: usize,
+    /// Relative to the top-left corner of the pattern's bounding box
+    cells: Vec<Position>,
+    /// Holds every cell instead of `cells` if they came from a macrocell file or an unbounded
+    /// generation, so that they're only flattened if they have to be
+    universe: Option<Universe>,
+}
+
+/// Read a pattern from a file in any of the supported formats
+pub fn read(path: &Path) -> Result<Pattern> {
+    let content = fs::read_to_string(path)?;
+    let format = Format::detect(Some(path), &content).ok_or(Error::UnknownFormat)?;
+    format.parse(&content)
+}
+
+/// Write a pattern to a file in the given format, replacing the file if it already exists
+pub fn write(path: &Path, format: Format, pattern: &Pattern) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    format.write(pattern, &mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Parse a rulestring, ignoring any bounded grid suffix, e.g. the `:T40,20` of `B3/S23:T40,20`
+fn parse_rule(rulestring: &str) -> Result<Rule> {
+    let rule = rulestring.split(':').next().unwrap_or(rulestring);
+    Ok(rule.parse()?)
+}
+
+/// The live cells of a glider heading down and to the right, for tests to read, write and run
+#[cfg(test)]
+pub(super) fn glider() -> Vec<Position> {
+    let cells = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+    cells.iter().map(|&(x, y)| Position(x, y)).collect()
+}
+
+/// Check that a `pattern` is the glider as described by the LifeWiki, name and all
+#[cfg(test)]
+fn assert_is_described_glider(pattern: &Pattern) {
+    assert_eq!(pattern.name(), Some("Glider"));
+    assert_eq!(pattern.author(), Some("Richard K. Guy"));
+    assert_eq!(
+        pattern.comments(),
+        ["The smallest, slowest and first discovered spaceship"]
+    );
+    assert_eq!((pattern.width(), pattern.height()), (3, 3));
+    assert_eq!(pattern.sorted_cells(), glider());
+}
+
+impl Format {
+    /// Prefers the header of the `content` if it's recognisable, and falls back to the extension of
+    /// the `path`
+    pub fn detect(path: Option<&Path>, content: &str) -> Option<Self> {
+        Self::from_header(content).or_else(|| Self::from_extension(path?))
+    }
+
+    pub fn parse(self, content: &str) -> Result<Pattern> {
+        match self {
+            Self::Rle => rle::parse(content),
+            Self::Plaintext => plaintext::parse(content),
+            Self::Life105 => life::parse_105(content),
+            Self::Life106 => life::parse_106(content),
+            Self::Macrocell => macrocell::parse(content),
+        }
+    }
+
+    pub fn write<W: Write>(self, pattern: &Pattern, out: &mut W) -> Result<()> {
+        match self {
+            Self::Rle => rle::write(pattern, out)?,
+            Self::Plaintext => plaintext::write(pattern, out)?,
+            Self::Life105 => life::write_105(pattern, out)?,
+            Self::Life106 => life::write_106(pattern, out)?,
+            Self::Macrocell => macrocell::write(pattern, out)?,
+        }
+        Ok(())
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Rle => "rle",
+            Self::Plaintext => "cells",
+            Self::Life105 | Self::Life106 => "lif",
+            Self::Macrocell => "mc",
+        }
+    }
+
+    fn from_header(content: &str) -> Option<Self> {
+        let mut lines = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty());
+        let first_line = lines.next()?;
+        if first_line.starts_with(life::HEADER_105) {
+            Some(Self::Life105)
+        } else if first_line.starts_with(life::HEADER_106) {
+            Some(Self::Life106)
+        } else if first_line.starts_with(macrocell::HEADER) {
+            Some(Self::Macrocell)
+        } else if first_line.starts_with('!') {
+            Some(Self::Plaintext)
+        } else {
+            // RLE files start with an optional block of comments followed by the header line
+            let rle_header = std::iter::once(first_line)
+                .chain(lines)
+                .find(|line| !line.starts_with('#'))?;
+            if rle_header.starts_with('x') {
+                Some(Self::Rle)
+            } else {
+                None
+            }
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rle" => Some(Self::Rle),
+            "cells" => Some(Self::Plaintext),
+            "lif" | "life" => Some(Self::Life106),
+            "mc" => Some(Self::Macrocell),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "rle" => Ok(Self::Rle),
+            "cells" | "plaintext" => Ok(Self::Plaintext),
+            "life105" => Ok(Self::Life105),
+            "life106" => Ok(Self::Life106),
+            "mc" | "macrocell" => Ok(Self::Macrocell),
+            _ => Err(Error::UnknownFormat),
+        }
+    }
+}
+
+impl Pattern {
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    pub fn set_author(&mut self, author: Option<String>) {
+        self.author = author;
+    }
+
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    pub fn push_comment(&mut self, comment: String) {
+        self.comments.push(comment);
+    }
+
+    pub fn rule(&self) -> Option<Rule> {
+        self.rule
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn cells(&self) -> Cow<'_, [Position]> {
+        match &self.universe {
+            Some(universe) => Cow::Owned(universe.live_cells(None)),
+            None => Cow::Borrowed(&self.cells),
+        }
+    }
+
+    /// Bring the pattern to life in `generation`, with its top-left corner at `offset`, or centred
+    /// if there is no `offset`. Cells which don't fit will wrap around according to the topology of
+    /// the generation, or be left out if it's a plane, or if they're too far away to have a
+    /// position at all.
+    pub fn place(&self, generation: &mut Generation, offset: Option<Position>) {
+        let offset = offset.unwrap_or_else(|| {
+            Position(
+                (generation.width() as isize - self.width() as isize) / 2,
+                (generation.height() as isize - self.height() as isize) / 2,
+            )
+        });
+        match &self.universe {
+            // an empty unbounded generation can just take the whole universe in one go
+            Some(universe)
+                if generation.topology() == Topology::Unbounded
+                    && generation.rule() == universe.rule()
+                    && generation.population() == 0 =>
+            {
+                let universe = universe.moved(offset, generation.index());
+                *generation =
+                    Generation::from_universe(generation.width(), generation.height(), universe);
+            }
+            _ => {
+                for &position in self.cells().iter() {
+                    if let Some(position) = position.checked_add(offset) {
+                        generation.set(position, Cell::Alive);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The live cells sorted into rows, top to bottom, and left to right within each row
+    fn sorted_cells(&self) -> Vec<Position> {
+        let mut cells = self.cells().into_owned();
+        cells.sort_by_key(|position| (position.y(), position.x()));
+        cells.dedup();
+        cells
+    }
+
+    /// One line per row of the bounding box, omitting any trailing dead cells from each row
+    fn write_rows<W: Write>(&self, out: &mut W, dead: char, alive: char) -> io::Result<()> {
+        let mut cells = self.sorted_cells().into_iter().peekable();
+        for y in 0..self.height() as isize {
+            let mut row = String::new();
+            while let Some(position) = cells.next_if(|position| position.y() == y) {
+                let x = position.x() as usize;
+                row.extend(std::iter::repeat_n(dead, x - row.len()));
+                row.push(alive);
+            }
+            writeln!(out, "{}", row)?;
+        }
+        Ok(())
+    }
+
+    /// Move the live cells so that the top-left corner of their bounding box is `(0, 0)`, and
+    /// shrink the bounding box to fit them
+    fn normalise(&mut self) {
+        if let Some(universe) = &mut self.universe {
+            let (min, max) = universe
+                .bounds()
+                .unwrap_or((Position(0, 0), Position(0, 0)));
+            *universe = universe.moved(Position(-min.x(), -min.y()), universe.index());
+            self.width = (max.x() - min.x()) as usize;
+            self.height = (max.y() - min.y()) as usize;
+            return;
+        }
+        let min_x = self.cells.iter().map(Position::x).min().unwrap_or(0);
+        let min_y = self.cells.iter().map(Position::y).min().unwrap_or(0);
+        for position in &mut self.cells {
+            *position = Position(position.x() - min_x, position.y() - min_y);
+        }
+        self.width = 0;
+        self.height = 0;
+        self.fit_to_cells();
+    }
+
+    /// Grow the bounding box of the pattern if needed, so that it contains all of its live cells
+    fn fit_to_cells(&mut self) {
+        for position in &self.cells {
+            self.width = self.width.max(position.x() as usize + 1);
+            self.height = self.height.max(position.y() as usize + 1);
+        }
+    }
+}
+
+impl From<&Generation> for Pattern {
+    /// Takes the whole grid as the bounding box, so that the pattern ends up in the same place
+    /// when it's placed into a generation of the same size. Unbounded generations don't have a
+    /// whole grid, so take just enough to fit all of their live cells.
+    fn from(generation: &Generation) -> Self {
+        let mut pattern = Self {
+            rule: Some(generation.rule()),
+            width: generation.width(),
+            height: generation.height(),
+            cells: match generation.universe() {
+                Some(_) => Vec::new(),
+                None => generation.live_cells(None),
+            },
+            universe: generation.universe().cloned(),
+            ..Self::default()
+        };
+        if generation.topology() == Topology::Unbounded {
+            pattern.normalise();
+        }
+        pattern
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Rule(err) => Some(err),
+            Self::UnknownFormat | Self::UnexpectedEof | Self::Syntax(_, _) => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => fmt::Display::fmt(err, f),
+            Self::UnknownFormat => f.write_str("unrecognised pattern file format"),
+            Self::UnexpectedEof => f.write_str("unexpected end of pattern file"),
+            Self::Syntax(line, reason) => write!(f, "line {}: {}", line, reason),
+            Self::Rule(err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Self::Io(source)
+    }
+}
+
+impl From<ParseRuleError> for Error {
+    fn from(source: ParseRuleError) -> Self {
+        Self::Rule(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leaves_out_cells_placed_too_far_away_to_have_a_position() {
+        let pattern = Format::Rle.parse("x = 3, y = 1\n3o!").unwrap();
+        let mut generation = Generation::filled(0, 8, 8, Rule::CONWAY, Cell::Dead).unwrap();
+        pattern.place(&mut generation, Some(Position(isize::MAX - 1, 3)));
+        // only the first two cells have positions, which wrap around to the right-hand columns
+        assert_eq!(
+            generation.live_cells(None),
+            [Position(6, 3), Position(7, 3)]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! The [Life 1.05](https://conwaylife.com/wiki/Life_1.05) and
+//! [Life 1.06](https://conwaylife.com/wiki/Life_1.06) formats
+
+use super::*;
+
+pub const HEADER_105: &str = "#Life 1.05";
+pub const HEADER_106: &str = "#Life 1.06";
+
+/// Blocks of cells, each introduced by a `#P x y` line giving the position of its top-left corner
+pub fn parse_105(content: &str) -> Result<Pattern> {
+    let mut pattern = Pattern::default();
+    let mut bounds = None;
+    let mut block_origin = None;
+    let mut y = 0;
+    for (line_no, line) in content.lines().enumerate() {
+        let (line_no, line) = (line_no + 1, line.trim());
+        if line.starts_with(HEADER_105) || line.is_empty() {
+            continue;
+        }
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut chars = directive.chars();
+            let kind = chars.next();
+            let args = chars.as_str().trim();
+            match kind {
+                Some('D') | Some('C') => pattern.comments.push(args.to_string()),
+                Some('N') => pattern.rule = Some(Rule::CONWAY),
+                Some('R') => pattern.rule = Some(parse_rule(args)?),
+                Some('P') => {
+                    block_origin = Some(parse_position(line_no, args)?);
+                    y = 0;
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let origin = block_origin.unwrap_or(Position(0, 0));
+        for (x, ch) in line.chars().enumerate() {
+            match ch {
+                '.' => {}
+                '*' => {
+                    let position = origin.checked_add(Position::from((x, y)));
+                    push_cell(&mut pattern, &mut bounds, line_no, position)?;
+                }
+                ch => {
+                    let reason = format!("unexpected '{}', expected '.' or '*'", ch);
+                    return Err(Error::Syntax(line_no, reason));
+                }
+            }
+        }
+        y += 1;
+    }
+    pattern.normalise();
+    Ok(pattern)
+}
+
+/// One `x y` line per live cell
+pub fn parse_106(content: &str) -> Result<Pattern> {
+    let mut pattern = Pattern::default();
+    let mut bounds = None;
+    for (line_no, line) in content.lines().enumerate() {
+        let (line_no, line) = (line_no + 1, line.trim());
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        let position = parse_position(line_no, line)?;
+        push_cell(&mut pattern, &mut bounds, line_no, Some(position))?;
+    }
+    pattern.normalise();
+    Ok(pattern)
+}
+
+/// Add a live cell to the `pattern`, as long as it doesn't take the `bounds` of its cells so far
+/// past [`MAX_SIZE`]. There's no `position` if it was too far away to have one.
+fn push_cell(
+    pattern: &mut Pattern,
+    bounds: &mut Option<(Position, Position)>,
+    line_no: usize,
+    position: Option<Position>,
+) -> Result<()> {
+    let too_big = || {
+        let reason = format!("pattern is bigger than {}x{}", MAX_SIZE, MAX_SIZE);
+        Error::Syntax(line_no, reason)
+    };
+    let position = position.ok_or_else(too_big)?;
+    let (min, max) = bounds.unwrap_or((position, position));
+    let (min, max) = (
+        Position(min.x().min(position.x()), min.y().min(position.y())),
+        Position(max.x().max(position.x()), max.y().max(position.y())),
+    );
+    let fits = |min: isize, max: isize| {
+        max.checked_sub(min)
+            .is_some_and(|span| (span as usize) < MAX_SIZE)
+    };
+    if !fits(min.x(), max.x()) || !fits(min.y(), max.y()) {
+        return Err(too_big());
+    }
+    *bounds = Some((min, max));
+    pattern.cells.push(position);
+    Ok(())
+}
+
+/// e.g. `-3 12`
+fn parse_position(line_no: usize, s: &str) -> Result<Position> {
+    let mut coords = s.split_whitespace().map(str::parse);
+    match (coords.next(), coords.next(), coords.next()) {
+        (Some(Ok(x)), Some(Ok(y)), None) => Ok(Position(x, y)),
+        (_, _, _) => {
+            let reason = format!("expected a position like \"-3 12\", not \"{}\"", s);
+            Err(Error::Syntax(line_no, reason))
+        }
+    }
+}
+
+pub fn write_105<W: io::Write>(pattern: &Pattern, out: &mut W) -> io::Result<()> {
+    writeln!(out, "{}", HEADER_105)?;
+    write_descriptions(pattern, out)?;
+    match pattern.rule() {
+        Some(Rule::CONWAY) | None => writeln!(out, "#N")?,
+        Some(rule) => writeln!(out, "#R {:#}", rule)?,
+    }
+    writeln!(out, "#P 0 0")?;
+    pattern.write_rows(out, '.', '*')
+}
+
+pub fn write_106<W: io::Write>(pattern: &Pattern, out: &mut W) -> io::Result<()> {
+    writeln!(out, "{}", HEADER_106)?;
+    write_descriptions(pattern, out)?;
+    for position in pattern.sorted_cells() {
+        writeln!(out, "{} {}", position.x(), position.y())?;
+    }
+    Ok(())
+}
+
+/// The name, author and comments of the `pattern`, as one `#D` line each
+fn write_descriptions<W: io::Write>(pattern: &Pattern, out: &mut W) -> io::Result<()> {
+    let descriptions = pattern.name().into_iter().chain(pattern.author());
+    for description in descriptions.chain(pattern.comments().iter().map(String::as_str)) {
+        writeln!(out, "#D {}", description)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_glider_split_across_blocks_as_life_105() {
+        let content = "#Life 1.05\n#D Glider\n#R 23/36\n#P -1 -1\n.*\n#P -1 0\n..*\n***\n";
+        let pattern = parse_105(content).unwrap();
+        assert_eq!(pattern.comments(), ["Glider"]);
+        assert_eq!(pattern.rule(), Some("B36/S23".parse().unwrap()));
+        assert_eq!((pattern.width(), pattern.height()), (3, 3));
+        assert_eq!(pattern.sorted_cells(), glider());
+    }
+
+    #[test]
+    fn writes_a_glider_the_same_as_it_was_read_as_life_105() {
+        let content = "#Life 1.05\n#D Glider\n#R 23/36\n#P 0 0\n.*\n..*\n***\n";
+        let mut out = Vec::new();
+        write_105(&parse_105(content).unwrap(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), content);
+    }
+
+    #[test]
+    fn parses_a_glider_around_the_origin_as_life_106() {
+        let content = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        let pattern = parse_106(content).unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (3, 3));
+        assert_eq!(pattern.sorted_cells(), glider());
+    }
+
+    #[test]
+    fn writes_a_glider_the_same_as_it_was_read_as_life_106() {
+        let content = "#Life 1.06\n1 0\n2 1\n0 2\n1 2\n2 2\n";
+        let mut out = Vec::new();
+        write_106(&parse_106(content).unwrap(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), content);
+    }
+
+    #[test]
+    fn rejects_cells_too_far_apart_to_fit_in_memory() {
+        let reason = "pattern is bigger than 65536x65536".to_string();
+        let content = "#Life 1.06\n0 0\n65535 -65535\n-1 0\n";
+        let err = parse_106(content).unwrap_err();
+        assert!(matches!(err, Error::Syntax(4, r) if r == reason));
+
+        let content = "#Life 1.06\n-9223372036854775808 0\n9223372036854775807 0\n";
+        let err = parse_106(content).unwrap_err();
+        assert!(matches!(err, Error::Syntax(3, r) if r == reason));
+
+        let content = "#Life 1.05\n#P 9223372036854775807 0\n**\n";
+        let err = parse_105(content).unwrap_err();
+        assert!(matches!(err, Error::Syntax(3, r) if r == reason));
+    }
+}
@@ -0,0 +1,348 @@
+//! Golly's [macrocell](https://conwaylife.com/wiki/Macrocell) format, which stores a pattern as a
+//! quadtree of distinct nodes, so that huge but regular patterns stay small
+
+use std::collections::HashMap;
+
+use crate::hashlife::{HashLife, NodeId, Universe};
+
+use super::*;
+
+pub const HEADER: &str = "[M2]";
+
+/// Nodes of this level are written out cell-by-cell, and bigger nodes as their 4 quadrants
+const LEAF_LEVEL: u8 = Universe::MIN_LEVEL;
+
+pub fn parse(content: &str) -> Result<Pattern> {
+    let (universe, mut pattern) = read(content)?;
+    pattern.universe = Some(universe);
+    pattern.normalise();
+    Ok(pattern)
+}
+
+pub fn write<W: io::Write>(pattern: &Pattern, out: &mut W) -> io::Result<()> {
+    match &pattern.universe {
+        Some(universe) => write_universe(universe, pattern, out),
+        None => {
+            let rule = pattern.rule().unwrap_or_default();
+            let universe = Universe::new(0, rule, &pattern.cells);
+            write_universe(&universe, pattern, out)
+        }
+    }
+}
+
+/// Write straight from a universe, without ever having to visit each individual cell, along with
+/// the name, author and comments of the `metadata`
+pub fn write_universe<W: io::Write>(
+    universe: &Universe,
+    metadata: &Pattern,
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "{} ({})", HEADER, env!("CARGO_PKG_NAME"))?;
+    if let Some(name) = metadata.name() {
+        writeln!(out, "#N {}", name)?;
+    }
+    if let Some(author) = metadata.author() {
+        writeln!(out, "#O {}", author)?;
+    }
+    for comment in metadata.comments() {
+        writeln!(out, "#C {}", comment)?;
+    }
+    write_nodes(universe, out)
+}
+
+/// Returns the universe along with a pattern holding any metadata, but no cells
+fn read(content: &str) -> Result<(Universe, Pattern)> {
+    let mut lines = content
+        .lines()
+        .enumerate()
+        .map(|(line_no, line)| (line_no + 1, line.trim()));
+    match lines.next() {
+        Some((_, line)) if line.starts_with(HEADER) => {}
+        Some((line_no, _)) => {
+            let reason = format!("expected a \"{}\" header", HEADER);
+            return Err(Error::Syntax(line_no, reason));
+        }
+        None => return Err(Error::UnexpectedEof),
+    }
+
+    let mut metadata = Pattern::default();
+    let mut index = 0;
+    // the rule has to be known before any of the nodes can be made
+    let mut engine = None;
+    // node `n` of the file is at `nodes[n - 1]`
+    let mut nodes = Vec::new();
+    for (line_no, line) in lines {
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut chars = directive.chars();
+            let kind = chars.next();
+            let args = chars.as_str().trim();
+            match kind {
+                Some('R') if engine.is_some() => {
+                    let reason = "the rule has to come before any nodes, which are made with it";
+                    return Err(Error::Syntax(line_no, reason.to_string()));
+                }
+                Some('R') => metadata.rule = Some(parse_rule(args)?),
+                Some('G') => {
+                    let reason = "expected a generation index like \"#G 12\"";
+                    index = args
+                        .parse()
+                        .map_err(|_| Error::Syntax(line_no, reason.to_string()))?;
+                }
+                Some('N') => metadata.name = Some(args.to_string()),
+                Some('O') => metadata.author = Some(args.to_string()),
+                Some('C') => metadata.comments.push(args.to_string()),
+                _ => {}
+            }
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let engine = engine.get_or_insert_with(|| HashLife::new(metadata.rule.unwrap_or_default()));
+        let id = if line.starts_with(|ch: char| ch.is_ascii_digit()) {
+            parse_branch(engine, &nodes, line_no, line)?
+        } else {
+            parse_leaf(engine, line_no, line)?
+        };
+        nodes.push(id);
+    }
+
+    let (engine, &root) = match (engine, nodes.last()) {
+        (Some(engine), Some(root)) => (engine, root),
+        (_, _) => return Err(Error::UnexpectedEof),
+    };
+    // Golly centres the root on (0, 0)
+    let half = 1 << (engine.level(root) - 1);
+    let universe = Universe::from_root(engine, root, Position(-half, -half), index);
+    Ok((universe, metadata))
+}
+
+/// e.g. `4 0 1 2 1`, for a node of the given level made of the given (previous) nodes, where `0`
+/// is an empty node
+fn parse_branch(
+    engine: &mut HashLife,
+    nodes: &[NodeId],
+    line_no: usize,
+    line: &str,
+) -> Result<NodeId> {
+    let syntax_err = |reason: &str| Error::Syntax(line_no, reason.to_string());
+    let numbers = line
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<std::result::Result<Vec<usize>, _>>()
+        .map_err(|_| syntax_err("expected a node like \"4 0 1 2 1\""))?;
+    let (level, child_numbers) = match numbers.as_slice() {
+        [level, children @ ..] if children.len() == 4 => (*level, children),
+        _ => return Err(syntax_err("expected a node like \"4 0 1 2 1\"")),
+    };
+    if level <= LEAF_LEVEL as usize || level > Universe::MAX_SEED_LEVEL as usize {
+        return Err(syntax_err("node level out of range"));
+    }
+
+    let child_level = level as u8 - 1;
+    let mut children = [0; 4];
+    for (child, &number) in children.iter_mut().zip(child_numbers) {
+        *child = match number {
+            0 => engine.empty(child_level),
+            number => *nodes
+                .get(number - 1)
+                .ok_or_else(|| syntax_err("reference to a node which isn't defined yet"))?,
+        };
+        if engine.level(*child) != child_level {
+            return Err(syntax_err("reference to a node of the wrong level"));
+        }
+    }
+    Ok(engine.join(children))
+}
+
+/// e.g. `.*$..*$***$`, for an 8x8 node
+fn parse_leaf(engine: &mut HashLife, line_no: usize, line: &str) -> Result<NodeId> {
+    let size = 1 << LEAF_LEVEL;
+    let mut cells = Vec::new();
+    let mut position = Position(0, 0);
+    for ch in line.chars() {
+        match ch {
+            '.' => position.0 += 1,
+            '*' if position.x() < size && position.y() < size => {
+                cells.push(position);
+                position.0 += 1;
+            }
+            '*' => {
+                let reason = format!("too many cells, expected at most {}x{}", size, size);
+                return Err(Error::Syntax(line_no, reason));
+            }
+            '$' => position = Position(0, position.y() + 1),
+            ch => {
+                let reason = format!("unexpected '{}', expected '.', '*' or '$'", ch);
+                return Err(Error::Syntax(line_no, reason));
+            }
+        }
+    }
+    Ok(engine.build(LEAF_LEVEL, Position(0, 0), &mut cells))
+}
+
+fn write_nodes<W: io::Write>(universe: &Universe, out: &mut W) -> io::Result<()> {
+    writeln!(out, "#R {}", universe.rule())?;
+    if universe.index() != 0 {
+        writeln!(out, "#G {}", universe.index())?;
+    }
+    let engine = universe.engine();
+    assert!(
+        engine.level(universe.root()) >= LEAF_LEVEL,
+        "Roots should never be smaller than a leaf"
+    );
+    if engine.population(universe.root()) == 0 {
+        // an empty leaf, as there has to be at least one node
+        return writeln!(out, "$");
+    }
+    write_node(&engine, universe.root(), &mut HashMap::new(), out)?;
+    Ok(())
+}
+
+/// Write a node after any of its children which haven't been written yet, returning its number
+fn write_node<W: io::Write>(
+    engine: &HashLife,
+    id: NodeId,
+    numbers: &mut HashMap<NodeId, usize>,
+    out: &mut W,
+) -> io::Result<usize> {
+    if engine.population(id) == 0 {
+        return Ok(0);
+    }
+    if let Some(&number) = numbers.get(&id) {
+        return Ok(number);
+    }
+
+    let level = engine.level(id);
+    if level == LEAF_LEVEL {
+        let size = 1 << LEAF_LEVEL;
+        let mut rows = (0..size)
+            .map(|y| {
+                let row = (0..size)
+                    .map(|x| match engine.cell(id, Position(x, y)) {
+                        Cell::Alive => '*',
+                        Cell::Dead => '.',
+                    })
+                    .collect::<String>();
+                format!("{}$", row.trim_end_matches('.'))
+            })
+            .collect::<Vec<_>>();
+        while rows.last().is_some_and(|row| row == "$") {
+            rows.pop();
+        }
+        writeln!(out, "{}", rows.concat())?;
+    } else {
+        let mut child_numbers = [0; 4];
+        for (number, &child) in child_numbers.iter_mut().zip(&engine.children(id)) {
+            *number = write_node(engine, child, numbers, out)?;
+        }
+        let [nw, ne, sw, se] = child_numbers;
+        writeln!(out, "{} {} {} {} {}", level, nw, ne, sw, se)?;
+    }
+
+    let number = numbers.len() + 1;
+    numbers.insert(id, number);
+    Ok(number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "\
+[M2] (conways-game-of-life)
+#N Glider
+#O Richard K. Guy
+#C The smallest, slowest and first discovered spaceship
+#R B3/S23
+.*$..*$***$
+";
+
+    #[test]
+    fn parses_a_glider() {
+        let pattern = parse(GLIDER).unwrap();
+        assert_is_described_glider(&pattern);
+        assert_eq!(pattern.rule(), Some(Rule::CONWAY));
+    }
+
+    #[test]
+    fn parses_a_glider_in_the_corner_of_a_bigger_node() {
+        let pattern = parse("[M2] (golly 4.2)\n#R B3/S23\n.*$..*$***$\n4 0 0 0 1\n").unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (3, 3));
+        assert_eq!(pattern.sorted_cells(), glider());
+    }
+
+    #[test]
+    fn writes_a_glider_the_same_as_it_was_read() {
+        let mut out = Vec::new();
+        write(&parse(GLIDER).unwrap(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), GLIDER);
+    }
+
+    #[test]
+    fn roots_stay_big_enough_to_write_and_read_back_after_a_jump() {
+        let mut generation = Generation::filled(0, 8, 8, Rule::CONWAY, Cell::Dead)
+            .unwrap()
+            .with_topology(Topology::Unbounded);
+        for &(x, y) in &[(3, 3), (4, 3), (3, 4), (4, 4)] {
+            generation.set(Position(x, y), Cell::Alive);
+        }
+        let generation = Generation::nth_after(&generation, 1);
+
+        let mut out = Vec::new();
+        write(&Pattern::from(&generation), &mut out).unwrap();
+        let pattern = parse(std::str::from_utf8(&out).unwrap()).unwrap();
+        let block = vec![
+            Position(0, 0),
+            Position(1, 0),
+            Position(0, 1),
+            Position(1, 1),
+        ];
+        assert_eq!(pattern.sorted_cells(), block);
+    }
+
+    #[test]
+    fn rejects_nodes_too_big_for_their_cells_to_have_positions() {
+        let mut content = "[M2]\n*$\n".to_string();
+        for level in 4..=70 {
+            content += &format!("{} {} 0 0 0\n", level, level - 3);
+        }
+        let reason = "node level out of range".to_string();
+        let err = parse(&content).unwrap_err();
+        assert!(matches!(err, Error::Syntax(60, r) if r == reason));
+    }
+
+    #[test]
+    fn the_biggest_nodes_can_still_be_stepped() {
+        // cells in opposite corners, which have to be surrounded twice over before stepping
+        let mut content = "[M2]\n*$\n$$$$$$$.......*$\n".to_string();
+        for level in 4..Universe::MAX_SEED_LEVEL as usize {
+            let (north_west, south_east) = (2 * (level - 4) + 1, 2 * (level - 4) + 2);
+            content += &format!("{} {} 0 0 0\n", level, north_west);
+            content += &format!("{} 0 0 0 {}\n", level, south_east);
+        }
+        // the last two nodes, given that every line after the header is a node
+        let nodes = content.lines().count() - 1;
+        let (level, north_west, south_east) = (Universe::MAX_SEED_LEVEL, nodes - 1, nodes);
+        content += &format!("{} {} 0 0 {}\n", level, north_west, south_east);
+
+        let pattern = parse(&content).unwrap();
+        let universe = pattern.universe.as_ref().unwrap();
+        assert_eq!(universe.population(), 2);
+        assert_eq!(Universe::nth_after(universe, 1).population(), 0);
+    }
+
+    #[test]
+    fn rejects_a_rule_after_the_nodes_it_should_have_made() {
+        let reason = "the rule has to come before any nodes, which are made with it".to_string();
+        let err = parse("[M2]\n.*$..*$***$\n#R B36/S23\n").unwrap_err();
+        assert!(matches!(err, Error::Syntax(3, r) if r == reason));
+    }
+
+    #[test]
+    fn ignores_the_bounded_grid_of_a_rule() {
+        let pattern = parse("[M2]\n#R B36/S23:T100,100\n.*$..*$***$\n").unwrap();
+        assert_eq!(pattern.rule(), Some("B36/S23".parse().unwrap()));
+    }
+}
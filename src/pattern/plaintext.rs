@@ -0,0 +1,79 @@
+//! The [plaintext](https://conwaylife.com/wiki/Plaintext) `.cells` format used by the LifeWiki
+
+use super::*;
+
+pub fn parse(content: &str) -> Result<Pattern> {
+    let mut pattern = Pattern::default();
+    let mut y = 0;
+    for (line_no, line) in content.lines().enumerate() {
+        let (line_no, line) = (line_no + 1, line.trim_end());
+        if let Some(comment) = line.strip_prefix('!') {
+            parse_comment(&mut pattern, comment.trim());
+            continue;
+        }
+        for (x, ch) in line.chars().enumerate() {
+            match ch {
+                '.' => {}
+                'O' | '*' => pattern.cells.push(Position::from((x, y))),
+                ch => {
+                    let reason = format!("unexpected '{}', expected '.' or 'O'", ch);
+                    return Err(Error::Syntax(line_no, reason));
+                }
+            }
+        }
+        pattern.width = pattern.width.max(line.chars().count());
+        y += 1;
+    }
+    pattern.height = y;
+    Ok(pattern)
+}
+
+/// e.g. `!Name: Glider`
+fn parse_comment(pattern: &mut Pattern, comment: &str) {
+    if let Some(name) = comment.strip_prefix("Name:") {
+        pattern.name = Some(name.trim().to_string());
+    } else if let Some(author) = comment.strip_prefix("Author:") {
+        pattern.author = Some(author.trim().to_string());
+    } else if !comment.is_empty() {
+        pattern.comments.push(comment.to_string());
+    }
+}
+
+pub fn write<W: io::Write>(pattern: &Pattern, out: &mut W) -> io::Result<()> {
+    if let Some(name) = pattern.name() {
+        writeln!(out, "!Name: {}", name)?;
+    }
+    if let Some(author) = pattern.author() {
+        writeln!(out, "!Author: {}", author)?;
+    }
+    for comment in pattern.comments() {
+        writeln!(out, "!{}", comment)?;
+    }
+    pattern.write_rows(out, '.', 'O')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "\
+!Name: Glider
+!Author: Richard K. Guy
+!The smallest, slowest and first discovered spaceship
+.O
+..O
+OOO
+";
+
+    #[test]
+    fn parses_a_glider() {
+        assert_is_described_glider(&parse(GLIDER).unwrap());
+    }
+
+    #[test]
+    fn writes_a_glider_the_same_as_it_was_read() {
+        let mut out = Vec::new();
+        write(&parse(GLIDER).unwrap(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), GLIDER);
+    }
+}
@@ -0,0 +1,298 @@
+//! The [RLE](https://conwaylife.com/wiki/Run_Length_Encoded) format used by Golly and the LifeWiki
+
+use super::*;
+
+pub fn parse(content: &str) -> Result<Pattern> {
+    let mut pattern = Pattern::default();
+    let mut header_seen = false;
+    let mut position = Position(0, 0);
+    let mut run_length: Option<isize> = None;
+
+    for (line_no, line) in content.lines().enumerate() {
+        let (line_no, line) = (line_no + 1, line.trim());
+        if !header_seen {
+            if line.starts_with('#') {
+                parse_comment(&mut pattern, line)?;
+            } else if line.starts_with('x') {
+                parse_header(&mut pattern, line_no, line)?;
+                header_seen = true;
+            } else if !line.is_empty() {
+                let reason = "expected a header line like \"x = 3, y = 3\"";
+                return Err(Error::Syntax(line_no, reason.to_string()));
+            }
+            continue;
+        }
+
+        for ch in line.chars() {
+            let count = run_length.unwrap_or(1);
+            // runs of cells must stay within the bounding box declared by the header
+            let (width, height) = (pattern.width as isize, pattern.height as isize);
+            let outside = match ch {
+                'b' | '.' => count > width - position.x(),
+                'o' | 'A' => count > width - position.x() || position.y() >= height,
+                '$' => count > height - position.y(),
+                _ => false,
+            };
+            if outside {
+                let reason = format!("'{}' run goes outside the {}x{} pattern", ch, width, height);
+                return Err(Error::Syntax(line_no, reason));
+            }
+            match ch {
+                '0'..='9' => {
+                    let digit = ch.to_digit(10).unwrap() as isize;
+                    let next_run_length = run_length
+                        .unwrap_or(0)
+                        .checked_mul(10)
+                        .and_then(|run_length| run_length.checked_add(digit));
+                    match next_run_length {
+                        Some(next_run_length) => run_length = Some(next_run_length),
+                        None => {
+                            let reason = "run length is too big".to_string();
+                            return Err(Error::Syntax(line_no, reason));
+                        }
+                    }
+                    continue;
+                }
+                'b' | '.' => position.0 += count,
+                'o' | 'A' => {
+                    for _ in 0..count {
+                        pattern.cells.push(position);
+                        position.0 += 1;
+                    }
+                }
+                '$' => position = Position(0, position.1 + count),
+                '!' => {
+                    pattern.fit_to_cells();
+                    return Ok(pattern);
+                }
+                ch if ch.is_whitespace() => {}
+                ch => {
+                    let reason = format!("unexpected '{}' in pattern data", ch);
+                    return Err(Error::Syntax(line_no, reason));
+                }
+            }
+            run_length = None;
+        }
+    }
+
+    if header_seen {
+        // be lenient if the terminating '!' is missing, as many other readers are
+        pattern.fit_to_cells();
+        Ok(pattern)
+    } else {
+        Err(Error::UnexpectedEof)
+    }
+}
+
+/// e.g. `#N Glider`
+fn parse_comment(pattern: &mut Pattern, line: &str) -> Result<()> {
+    let mut chars = line.chars().skip(1);
+    let kind = chars.next();
+    let text = chars.collect::<String>().trim().to_string();
+    match kind {
+        Some('N') => pattern.name = Some(text),
+        Some('O') => pattern.author = Some(text),
+        Some('C') | Some('c') => pattern.comments.push(text),
+        Some('r') => pattern.rule = Some(parse_rule(&text)?),
+        // "#P" and "#R" give the position of the pattern, which is ignored in favour of centring it
+        _ => {}
+    }
+    Ok(())
+}
+
+/// e.g. `x = 3, y = 3, rule = B3/S23`
+fn parse_header(pattern: &mut Pattern, line_no: usize, line: &str) -> Result<()> {
+    let syntax_err = |reason: &str| Error::Syntax(line_no, reason.to_string());
+    let mut entries = line;
+    while !entries.trim().is_empty() {
+        let (key, rest) = entries
+            .split_once('=')
+            .ok_or_else(|| syntax_err("expected header entries like \"x = 3\""))?;
+        let key = key.trim();
+        // the rule is always last, and may have commas of its own, e.g. "B3/S23:T40,20"
+        let (value, rest) = match key {
+            "rule" => (rest, ""),
+            _ => rest.split_once(',').unwrap_or((rest, "")),
+        };
+        let value = value.trim();
+        entries = rest;
+        match key {
+            "x" | "y" if value.parse().is_ok_and(|size: usize| size > MAX_SIZE) => {
+                let reason = format!("pattern is bigger than {}x{}", MAX_SIZE, MAX_SIZE);
+                return Err(Error::Syntax(line_no, reason));
+            }
+            "x" => {
+                pattern.width = value
+                    .parse()
+                    .map_err(|_| syntax_err("expected a width like \"x = 3\""))?;
+            }
+            "y" => {
+                pattern.height = value
+                    .parse()
+                    .map_err(|_| syntax_err("expected a height like \"y = 3\""))?;
+            }
+            "rule" => pattern.rule = Some(parse_rule(value)?),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn write<W: io::Write>(pattern: &Pattern, out: &mut W) -> io::Result<()> {
+    /// Recommended maximum, so that patterns can be pasted into emails and forums
+    const MAX_LINE_LENGTH: usize = 70;
+
+    if let Some(name) = pattern.name() {
+        writeln!(out, "#N {}", name)?;
+    }
+    if let Some(author) = pattern.author() {
+        writeln!(out, "#O {}", author)?;
+    }
+    for comment in pattern.comments() {
+        writeln!(out, "#C {}", comment)?;
+    }
+    let rule = pattern.rule().unwrap_or_default();
+    writeln!(
+        out,
+        "x = {}, y = {}, rule = {}",
+        pattern.width(),
+        pattern.height(),
+        rule
+    )?;
+
+    let mut line = String::new();
+    for run in runs(&pattern.sorted_cells()) {
+        if line.len() + run.len() > MAX_LINE_LENGTH {
+            writeln!(out, "{}", line)?;
+            line.clear();
+        }
+        line.push_str(&run);
+    }
+    writeln!(out, "{}", line)
+}
+
+/// e.g. `["bo", "$", "2bo", "$", "3o", "!"]` for a glider, given its live cells in row order
+fn runs(sorted_cells: &[Position]) -> Vec<String> {
+    let run = |length: isize, tag: char| match length {
+        1 => tag.to_string(),
+        _ => format!("{}{}", length, tag),
+    };
+
+    let mut runs = Vec::new();
+    let mut cursor = Position(0, 0);
+    let mut cells = sorted_cells.iter().peekable();
+    while let Some(&start) = cells.next() {
+        if start.y() > cursor.y() {
+            runs.push(run(start.y() - cursor.y(), '$'));
+            cursor = Position(0, start.y());
+        }
+        if start.x() > cursor.x() {
+            runs.push(run(start.x() - cursor.x(), 'b'));
+        }
+        let mut end = start + Position(1, 0);
+        while cells.next_if(|&&position| position == end).is_some() {
+            end += Position(1, 0);
+        }
+        runs.push(run(end.x() - start.x(), 'o'));
+        cursor = end;
+    }
+    runs.push("!".to_string());
+    runs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "\
+#N Glider
+#O Richard K. Guy
+#C The smallest, slowest and first discovered spaceship
+x = 3, y = 3, rule = B3/S23
+bo$2bo$3o!
+";
+
+    #[test]
+    fn parses_a_glider() {
+        let pattern = parse(GLIDER).unwrap();
+        assert_is_described_glider(&pattern);
+        assert_eq!(pattern.rule(), Some(Rule::CONWAY));
+    }
+
+    #[test]
+    fn writes_a_glider_the_same_as_it_was_read() {
+        let mut out = Vec::new();
+        write(&parse(GLIDER).unwrap(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), GLIDER);
+    }
+
+    #[test]
+    fn ignores_the_bounded_grid_of_a_rule() {
+        let pattern = parse("x = 3, y = 3, rule = B36/S23:T40,20\nbo$2bo$3o!").unwrap();
+        assert_eq!(pattern.rule(), Some("B36/S23".parse().unwrap()));
+    }
+
+    #[test]
+    fn reads_the_rule_from_a_comment_the_same_as_from_the_header() {
+        let pattern = parse("#r B36/S23:T40,20\nx = 3, y = 3\nbo$2bo$3o!").unwrap();
+        assert_eq!(pattern.rule(), Some("B36/S23".parse().unwrap()));
+        let err = parse("#r B3/S2x\nx = 3, y = 3\nbo$2bo$3o!").unwrap_err();
+        assert!(matches!(err, Error::Rule(_)));
+    }
+
+    #[test]
+    fn rejects_run_lengths_too_big_to_count() {
+        let reason = "run length is too big".to_string();
+        let err = parse("x = 1, y = 1\n99999999999999999999o!").unwrap_err();
+        assert!(matches!(err, Error::Syntax(2, r) if r == reason));
+    }
+
+    #[test]
+    fn rejects_headers_too_big_to_fit_in_memory() {
+        let reason = "pattern is bigger than 65536x65536".to_string();
+        let err = parse(
+            "x = 3, y = 99999999999
+bo$2bo$3o!",
+        )
+        .unwrap_err();
+        assert!(matches!(err, Error::Syntax(1, r) if r == reason));
+    }
+
+    #[test]
+    fn rejects_runs_which_go_outside_the_pattern() {
+        for (content, reason) in [
+            (
+                "x = 3, y = 3
+999999999999o!",
+                "'o' run goes outside the 3x3 pattern",
+            ),
+            (
+                "x = 3, y = 3
+bo$2bo$4o!",
+                "'o' run goes outside the 3x3 pattern",
+            ),
+            (
+                "x = 3, y = 3
+2$bo$o!",
+                "'o' run goes outside the 3x3 pattern",
+            ),
+            (
+                "x = 3, y = 3
+4b!",
+                "'b' run goes outside the 3x3 pattern",
+            ),
+            (
+                "x = 3, y = 3
+999999999999$!",
+                "'$' run goes outside the 3x3 pattern",
+            ),
+        ] {
+            let err = parse(content).unwrap_err();
+            assert!(
+                matches!(err, Error::Syntax(2, r) if r == reason),
+                "{}",
+                content
+            );
+        }
+    }
+}